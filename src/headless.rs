@@ -0,0 +1,74 @@
+use crate::logic::input::{InputSource, PlayerInput};
+use crate::logic::rng::GameRng;
+use crate::logic::state::GameState;
+use crate::GamePlugin;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::utils::{Duration, Instant};
+use std::collections::VecDeque;
+
+// Runs the game without a window or renderer, one fixed tick at a time,
+// so a test can script thousands of ticks and then check on the world.
+pub struct HeadlessGame {
+    pub app: App,
+    // The time is moved forward by hand so every update is exactly one tick
+    now: Instant,
+    tick_length: Duration,
+}
+
+impl HeadlessGame {
+    pub fn new(seed: u64) -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(GamePlugin)
+            .insert_resource(GameRng::new(seed));
+
+        // Skip the main menu and go straight into a game
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Playing);
+
+        let now = Instant::now();
+        let tick_length = app.world.resource::<FixedTime>().period;
+
+        // Time doesn't move on the first update, so this only sets up the game without ticking it
+        app.insert_resource(TimeUpdateStrategy::ManualInstant(now));
+        app.update();
+
+        Self {
+            app,
+            now,
+            tick_length,
+        }
+    }
+
+    // Runs a single fixed tick with the player doing `input`, then the rest of the frame.
+    pub fn tick(&mut self, input: PlayerInput) {
+        self.now += self.tick_length;
+
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualInstant(self.now))
+            .insert_resource(InputSource::Playback(VecDeque::from([input])));
+        self.app.update();
+    }
+
+    pub fn count<T: Component>(&mut self) -> usize {
+        self.app
+            .world
+            .query_filtered::<(), With<T>>()
+            .iter(&self.app.world)
+            .count()
+    }
+
+    pub fn state(&self) -> GameState {
+        self.app.world.resource::<State<GameState>>().0
+    }
+
+    // Goes back to Playing on the next tick, the same as pressing R on the game over screen
+    pub fn restart(&mut self) {
+        self.app
+            .world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Playing);
+    }
+}
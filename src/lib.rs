@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+use logic::state::GameState;
+
+pub const WINDOWHEIGHT: f32 = 1000.0;
+pub const WINDOWWIDTH: f32 = 1200.0;
+
+pub mod headless;
+pub mod logic;
+
+// All of the gameplay, without a window or anything that draws.
+// main adds the window, camera and assets on top of this, and headless runs it as is.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        // Blank handles until something loads the real assets
+        app.init_resource::<EnemySprite>()
+            .init_resource::<BulletSprite>()
+            .init_resource::<TurretSprite>()
+            .init_resource::<AmmoSprite>()
+            .init_resource::<PlayerSprite>()
+            .init_resource::<WallSprites>()
+            .init_resource::<UiFont>()
+            .add_system_to_schedule(
+                CoreSchedule::FixedUpdate,
+                flashing.run_if(in_state(GameState::Playing)),
+            )
+            .add_plugin(logic::state::StatePlugin)
+            .add_plugin(logic::menu::MenuPlugin)
+            .add_plugin(logic::rng::RngPlugin)
+            .add_plugin(logic::input::PlayerInputPlugin)
+            .add_plugin(logic::player::PlayerPlugin)
+            .add_plugin(logic::walls::WallsPlugin)
+            .add_plugin(logic::bullet::BulletPlugin)
+            .add_plugin(logic::enemy::EnemyPlugin)
+            .add_plugin(logic::score::ScorePlugin)
+            .add_plugin(logic::ammo::AmmoPlugin)
+            .add_plugin(logic::health::HealthPlugin)
+            .add_plugin(logic::lives::LivesPlugin)
+            .add_plugin(logic::physics::PhysicsPlugin);
+    }
+}
+
+// Enemies spawning in and anything that just got hurt should flash
+#[derive(Component)]
+pub struct Flashing {
+    flashed: bool,
+    // The timer refers to how long it takes between flashes
+    timer: Timer,
+}
+
+fn flashing(mut query: Query<(&mut Sprite, &mut Flashing)>, fixed_time: Res<FixedTime>) {
+    for (mut sprite, mut flashing) in &mut query {
+        if flashing.timer.tick(fixed_time.period).finished() {
+            if !flashing.flashed {
+                sprite.color = Color::BLACK;
+            } else {
+                sprite.color = Color::CRIMSON;
+            }
+            flashing.flashed = !flashing.flashed;
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct EnemySprite(pub Handle<Image>);
+
+#[derive(Resource, Default)]
+pub struct BulletSprite(pub Handle<Image>);
+
+#[derive(Resource, Default)]
+pub struct TurretSprite(pub Handle<Image>);
+
+#[derive(Resource, Default)]
+pub struct AmmoSprite(pub Handle<Image>);
+
+#[derive(Resource, Default)]
+pub struct PlayerSprite(pub Handle<Image>);
+
+#[derive(Resource, Default)]
+pub struct WallSprites {
+    pub horizontal: Handle<Image>,
+    pub vertical: Handle<Image>,
+    pub top_left: Handle<Image>,
+    pub top_right: Handle<Image>,
+    pub bottom_left: Handle<Image>,
+    pub bottom_right: Handle<Image>,
+}
+
+#[derive(Resource, Default)]
+pub struct UiFont(pub Handle<Font>);
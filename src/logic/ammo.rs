@@ -1,6 +1,10 @@
+use crate::logic::bullet::{BulletAnnihilationEvent, BulletCollisionSet};
 use crate::logic::enemy::EnemyDeathEvent;
-use crate::logic::player::{Player, STARTING_AMMO};
-use crate::AmmoSprite;
+use crate::logic::health::DeathSet;
+use crate::logic::physics::{Collider, ColliderType, CollisionLayers, CollisionTag};
+use crate::logic::player::{Player, STARTING_AMMO, WEAPONS};
+use crate::logic::state::{despawn_all, GameState, NewGame};
+use crate::{AmmoSprite, UiFont};
 use bevy::prelude::*;
 
 pub struct AmmoPlugin;
@@ -10,22 +14,40 @@ pub const BULLET_HEIGHT: f32 = 30.0;
 
 impl Plugin for AmmoPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(spawn_ammo)
-            .add_startup_system(show_ammo_ui)
-            .add_system(update_ammo_ui);
+        app.add_system_to_schedule(
+            CoreSchedule::FixedUpdate,
+            spawn_ammo
+                .after(BulletCollisionSet)
+                .after(DeathSet)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_system_to_schedule(NewGame, despawn_all::<Ammo>)
+        .add_startup_system(show_ammo_ui)
+        .add_system(update_ammo_ui);
     }
 }
 
 #[derive(Component)]
 pub struct Ammo;
 
-// Ammo spawns from dead enemies
+// Ammo spawns from dead enemies, and one bullet's worth drops
+// wherever two bullets destroy each other so the ammo isn't lost for good
 fn spawn_ammo(
     mut enemy_death_events: EventReader<EnemyDeathEvent>,
+    mut annihilation_events: EventReader<BulletAnnihilationEvent>,
     mut commands: Commands,
     ammo_sprite: Res<AmmoSprite>,
 ) {
-    for enemy_death_event in enemy_death_events.iter() {
+    let drop_positions = enemy_death_events
+        .iter()
+        .map(|enemy_death_event| enemy_death_event.death_position)
+        .chain(
+            annihilation_events
+                .iter()
+                .map(|annihilation_event| annihilation_event.position),
+        );
+
+    for drop_position in drop_positions {
         commands
             .spawn(SpriteBundle {
                 sprite: Sprite {
@@ -33,23 +55,31 @@ fn spawn_ammo(
                     ..Default::default()
                 },
                 texture: ammo_sprite.0.clone(),
-                transform: Transform::from_translation(enemy_death_event.death_position),
+                transform: Transform::from_translation(drop_position),
                 ..Default::default()
             })
-            .insert(Ammo);
+            .insert(Ammo)
+            .insert(Collider::aabb(Vec2::new(BULLET_WIDTH, BULLET_HEIGHT)))
+            .insert(ColliderType::Sensor)
+            .insert(CollisionTag::Ammo)
+            // Only the player can sense ammo, bullets and enemies pass right over it
+            .insert(CollisionLayers::new(
+                CollisionLayers::AMMO,
+                CollisionLayers::PLAYER,
+            ));
     }
 }
 
 #[derive(Component)]
 pub struct AmmoUI;
 
-// For now ammo is just a number in the bottom right
-fn show_ammo_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+// For now ammo is just a number in the bottom right, with the weapon's name next to it
+fn show_ammo_ui(mut commands: Commands, ui_font: Res<UiFont>) {
     let score_text: Text = Text::from_sections([
         TextSection::new(
             "Ammo: ",
             TextStyle {
-                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font: ui_font.0.clone(),
                 font_size: 60.0,
                 color: Color::WHITE,
             },
@@ -57,11 +87,19 @@ fn show_ammo_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
         TextSection::new(
             STARTING_AMMO.to_string(),
             TextStyle {
-                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font: ui_font.0.clone(),
                 font_size: 60.0,
                 color: Color::BLUE,
             },
         ),
+        TextSection::new(
+            format!(" {}", WEAPONS[0].name),
+            TextStyle {
+                font: ui_font.0.clone(),
+                font_size: 40.0,
+                color: Color::WHITE,
+            },
+        ),
     ]);
 
     commands
@@ -95,5 +133,6 @@ fn update_ammo_ui(
     // This requires a loop because most of the time no player component is changing
     for player in &player_query {
         ammo_ui.sections[1].value = player.ammo.to_string();
+        ammo_ui.sections[2].value = format!(" {}", WEAPONS[player.weapon].name);
     }
 }
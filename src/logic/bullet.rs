@@ -1,17 +1,66 @@
-use crate::logic::enemy::{Enemy, EnemyDeathEvent, ShootingEnemy, ENEMY_SIZE};
-use crate::logic::player::{Player, PLAYER_SIZE};
+use crate::logic::health::{DamageEvent, DamageSet, Hurtbox};
+use crate::logic::menu::GameSettings;
+use crate::logic::physics::{
+    CastFilter, Collider, ColliderType, CollisionEvent, CollisionLayers, CollisionTag,
+    ContinuousCollision, Movement, PhysicsQuery, PhysicsSet, SWEEP_SKIN,
+};
+use crate::logic::player::Player;
+use crate::logic::state::{despawn_all, GameState, NewGame};
+use crate::BulletSprite;
 use bevy::prelude::*;
-use bevy::sprite::collide_aabb::collide;
+use bevy::utils::HashSet;
+use std::time::Duration;
 
-pub const BULLET_SIZE: f32 = 20.0;
+// How long it takes an expired bullet to fade away
+const BULLET_FADE_SECONDS: f32 = 0.5;
+// The angle in radians between the pieces of a bullet that split
+const SPLIT_SPREAD: f32 = 0.35;
+// How many lives any bullet takes from the player, no matter what kind it is
+const PLAYER_BULLET_DAMAGE: u32 = 1;
 
 pub struct BulletPlugin;
 
+// Systems that send ShootingEvents run in this set in the fixed schedule.
+#[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
+pub struct ShootingSet;
+
+// Systems that react to what bullets hit run after this set in the fixed schedule.
+#[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
+pub struct BulletCollisionSet;
+
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(bullet_normal_enemy_collisions)
-            .add_system(bullet_shooting_enemy_collisions)
-            .add_system(bullet_player_collisions);
+        app.add_event::<ShootingEvent>()
+            .add_event::<BulletAnnihilationEvent>()
+            .add_event::<ExplosionEvent>()
+            .init_resource::<FriendlyFire>()
+            .init_resource::<SpentBullets>()
+            .add_system_to_schedule(
+                CoreSchedule::FixedUpdate,
+                shoot
+                    .after(ShootingSet)
+                    .before(PhysicsSet)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems_to_schedule(
+                CoreSchedule::FixedUpdate,
+                (
+                    age_bullets,
+                    count_bounces,
+                    expire_bullets,
+                    fade_bullets,
+                    split_bullets,
+                    bullet_bullet_collisions,
+                    bullet_hurtbox_collisions.in_set(DamageSet),
+                    explode.in_set(DamageSet),
+                    despawn_spent_bullets,
+                )
+                    .chain()
+                    .in_set(BulletCollisionSet)
+                    .after(PhysicsSet)
+                    .distributive_run_if(in_state(GameState::Playing)),
+            )
+            .add_system_to_schedule(NewGame, despawn_all::<Bullet>);
     }
 }
 
@@ -19,78 +68,550 @@ impl Plugin for BulletPlugin {
 #[derive(Component)]
 pub struct Bullet;
 
-// When the bullet hits an enemy destroy both the
-// bullet and the enemy.
-fn bullet_normal_enemy_collisions(
-    enemy_query: Query<(&Transform, Entity), With<Enemy>>,
-    bullet_query: Query<(&Transform, Entity), (With<Bullet>, Without<Enemy>)>,
-    mut add_to_score: EventWriter<EnemyDeathEvent>,
+// What a bullet does when it hits something
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectileEffect {
+    // Stops at the first enemy it hits
+    None,
+    // Goes straight through enemies, only walls and other bullets stop it
+    Piercing,
+    // Kills every enemy within the radius of the enemy it hits
+    Explosive { radius: f32 },
+    // Breaks into smaller bullets the first time it bounces
+    Splitting { pieces: u8 },
+}
+
+// Everything about a kind of bullet. It gets sent along with the ShootingEvent
+// and stays on the bullet so the collision systems know what it does.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct ProjectileKind {
+    pub name: &'static str,
+    // The width of the bullet
+    pub size: f32,
+    pub speed: f32,
+    // How much health a hit takes off an enemy, a player only ever loses one life to a bullet
+    pub damage: u32,
+    // How much speed the bullet keeps when it bounces
+    pub restitution: f32,
+    pub effect: ProjectileEffect,
+}
+
+impl ProjectileKind {
+    pub const NORMAL: ProjectileKind = ProjectileKind {
+        name: "Normal",
+        size: 20.0,
+        speed: 400.0,
+        damage: 1,
+        restitution: 1.0,
+        effect: ProjectileEffect::None,
+    };
+
+    pub const PIERCING: ProjectileKind = ProjectileKind {
+        name: "Piercing",
+        size: 14.0,
+        speed: 600.0,
+        damage: 1,
+        restitution: 1.0,
+        effect: ProjectileEffect::Piercing,
+    };
+
+    // Slow and heavy, it loses some speed every bounce
+    pub const EXPLOSIVE: ProjectileKind = ProjectileKind {
+        name: "Explosive",
+        size: 28.0,
+        speed: 300.0,
+        damage: 2,
+        restitution: 0.8,
+        effect: ProjectileEffect::Explosive { radius: 120.0 },
+    };
+
+    pub const SPLITTING: ProjectileKind = ProjectileKind {
+        name: "Splitting",
+        size: 24.0,
+        speed: 400.0,
+        damage: 1,
+        restitution: 1.0,
+        effect: ProjectileEffect::Splitting { pieces: 3 },
+    };
+
+    // What a splitting bullet breaks into
+    const SPLIT_PIECE: ProjectileKind = ProjectileKind {
+        name: "Split piece",
+        size: 12.0,
+        speed: 450.0,
+        damage: 1,
+        restitution: 1.0,
+        effect: ProjectileEffect::None,
+    };
+}
+
+// Who fired the bullet. The shooter might not exist anymore by the time the bullet hits something.
+#[derive(Component, Clone, Copy)]
+pub struct Shooter {
+    pub entity: Entity,
+    pub is_player: bool,
+}
+
+// How long the bullet has been flying for
+#[derive(Component, Default)]
+pub struct BulletAge(pub Duration);
+
+// How many times the bullet has bounced off of something
+#[derive(Component, Default)]
+pub struct Bounces(pub u32);
+
+// Everything a piercing bullet has gone through already, so it only hurts each of them once
+#[derive(Component, Default)]
+struct Pierced(HashSet<Entity>);
+
+// Specifies that the entity can shoot, and what kind of bullet it shoots
+pub struct ShootingEvent {
+    pub shooter: Entity,
+    pub kind: ProjectileKind,
+}
+
+// Spawn a bullet that moves in the direction the transform is facing.
+// This runs after everything in the ShootingSet and before physics, so the bullet spawns on the same tick.
+fn shoot(
+    mut shooting_event: EventReader<ShootingEvent>,
     mut commands: Commands,
+    transform_query: Query<&Transform>,
+    player_query: Query<(), With<Player>>,
+    physics_query: PhysicsQuery,
+    bullet_sprite: Res<BulletSprite>,
 ) {
-    for (enemy_transform, enemy_entity) in &enemy_query {
-        for (bullet_transform, bullet_entity) in &bullet_query {
-            if let Some(_collision) = collide(
-                bullet_transform.translation,
-                Vec2::new(BULLET_SIZE, BULLET_SIZE),
-                enemy_transform.translation,
-                Vec2::new(ENEMY_SIZE, ENEMY_SIZE),
-            ) {
-                commands.entity(enemy_entity).despawn();
-                commands.entity(bullet_entity).despawn();
-                add_to_score.send(EnemyDeathEvent {
-                    death_position: bullet_transform.translation,
-                });
-            }
+    for ShootingEvent { shooter, kind } in shooting_event.iter() {
+        let transform = transform_query
+            .get_component::<Transform>(*shooter)
+            .expect("Everything that shoots needs a transform.");
+
+        // Spawn the bullet ahead of the transform so that it does not
+        // immediately collide with it, unless that would put it inside of a wall.
+        let spawn_position = physics_query
+            .cast_aabb(
+                transform.translation.truncate(),
+                Vec2::new(kind.size, kind.size),
+                transform.local_y().truncate(),
+                50.0,
+                &CastFilter::new(CollisionLayers::WALL).excluding(*shooter),
+            )
+            .map_or(
+                (transform.translation + transform.local_y() * 50.0).truncate(),
+                |hit| hit.point + hit.normal * SWEEP_SKIN,
+            );
+
+        spawn_bullet(
+            &mut commands,
+            &bullet_sprite,
+            spawn_position.extend(transform.translation.z),
+            transform.local_y(),
+            *kind,
+            Shooter {
+                entity: *shooter,
+                is_player: player_query.contains(*shooter),
+            },
+        );
+    }
+}
+
+// Spawns a bullet of the given kind heading off in the direction, which should be normalized
+pub fn spawn_bullet(
+    commands: &mut Commands,
+    bullet_sprite: &BulletSprite,
+    position: Vec3,
+    direction: Vec3,
+    kind: ProjectileKind,
+    shooter: Shooter,
+) -> Entity {
+    let bullet = commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(kind.size, kind.size)),
+                ..Default::default()
+            },
+            texture: bullet_sprite.0.clone(),
+            transform: Transform::from_translation(position),
+            ..Default::default()
+        })
+        .insert(Bullet)
+        .insert(kind)
+        .insert(shooter)
+        .insert(BulletAge::default())
+        .insert(Bounces::default())
+        .insert(Movement {
+            velocity: direction * kind.speed,
+        })
+        .insert(Collider {
+            restitution: kind.restitution,
+            ..Collider::circle(kind.size / 2.0)
+        })
+        .insert(ColliderType::Reflect)
+        .insert(CollisionTag::Bullet)
+        .insert(CollisionLayers::new(
+            CollisionLayers::BULLET,
+            CollisionLayers::ALL,
+        ))
+        .insert(ContinuousCollision)
+        .id();
+
+    if kind.effect == ProjectileEffect::Piercing {
+        commands.entity(bullet).insert(Pierced::default());
+    }
+
+    bullet
+}
+
+// Decides which bullets can hurt what
+#[derive(Resource)]
+pub struct FriendlyFire {
+    // A bullet can't hurt whoever shot it until it's been flying for this long
+    // or has bounced this many times, whichever comes first
+    pub shooter_grace_period: Duration,
+    pub shooter_grace_bounces: u32,
+    // Whether the turrets' bullets kill other enemies
+    pub enemy_bullets_hit_enemies: bool,
+}
+
+impl Default for FriendlyFire {
+    fn default() -> Self {
+        FriendlyFire {
+            shooter_grace_period: Duration::from_millis(250),
+            shooter_grace_bounces: 1,
+            enemy_bullets_hit_enemies: true,
+        }
+    }
+}
+
+impl FriendlyFire {
+    fn can_hit(
+        &self,
+        (shooter, age, bounces): (&Shooter, &BulletAge, &Bounces),
+        target: Entity,
+        target_is_enemy: bool,
+    ) -> bool {
+        if shooter.entity == target {
+            return age.0 >= self.shooter_grace_period || bounces.0 >= self.shooter_grace_bounces;
+        }
+
+        if target_is_enemy && !shooter.is_player {
+            return self.enemy_bullets_hit_enemies;
+        }
+
+        true
+    }
+}
+
+fn age_bullets(mut bullet_query: Query<&mut BulletAge>, fixed_time: Res<FixedTime>) {
+    for mut bullet_age in &mut bullet_query {
+        bullet_age.0 += fixed_time.period;
+    }
+}
+
+// Bullets bounce whenever they reflect off of something.
+// Hitting a corner reflects off of two walls at once, but that still only counts as one bounce.
+fn count_bounces(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut bullet_query: Query<&mut Bounces>,
+) {
+    let mut bounced_bullets = HashSet::default();
+
+    for collision_event in collision_events.iter() {
+        if collision_event.kind != ColliderType::Reflect
+            || !bounced_bullets.insert(collision_event.a)
+        {
+            continue;
+        }
+
+        if let Ok(mut bounces) = bullet_query.get_mut(collision_event.a) {
+            bounces.0 += 1;
         }
     }
 }
 
-// When the bullet hits an enemy destroy both the
-// bullet and the enemy.
-fn bullet_shooting_enemy_collisions(
-    enemy_query: Query<(&Transform, Entity), With<ShootingEnemy>>,
-    bullet_query: Query<(&Transform, Entity), (With<Bullet>, Without<ShootingEnemy>)>,
-    mut add_to_score: EventWriter<EnemyDeathEvent>,
+// How long bullets last before they fade away. None means there's no limit.
+// A bullet expires as soon as it runs out of either one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BulletLimits {
+    pub lifetime: Option<Duration>,
+    pub max_bounces: Option<u32>,
+}
+
+impl BulletLimits {
+    // Bullets bounce around until they hit something
+    pub const FOREVER: BulletLimits = BulletLimits {
+        lifetime: None,
+        max_bounces: None,
+    };
+
+    fn expired(&self, age: &BulletAge, bounces: &Bounces) -> bool {
+        let out_of_time = matches!(self.lifetime, Some(lifetime) if age.0 >= lifetime);
+        let out_of_bounces =
+            matches!(self.max_bounces, Some(max_bounces) if bounces.0 >= max_bounces);
+
+        out_of_time || out_of_bounces
+    }
+}
+
+// An expired bullet that stops where it is and fades out before getting despawned
+#[derive(Component)]
+struct Fading {
+    timer: Timer,
+}
+
+fn expire_bullets(
+    bullet_query: Query<(Entity, &BulletAge, &Bounces), Without<Fading>>,
+    game_settings: Res<GameSettings>,
     mut commands: Commands,
 ) {
-    for (enemy_transform, enemy_entity) in &enemy_query {
-        for (bullet_transform, bullet_entity) in &bullet_query {
-            if let Some(_collision) = collide(
-                bullet_transform.translation,
-                Vec2::new(BULLET_SIZE, BULLET_SIZE),
-                enemy_transform.translation,
-                Vec2::new(ENEMY_SIZE, ENEMY_SIZE),
-            ) {
-                commands.entity(enemy_entity).despawn();
-                commands.entity(bullet_entity).despawn();
-                add_to_score.send(EnemyDeathEvent {
-                    death_position: bullet_transform.translation,
+    for (entity, age, bounces) in &bullet_query {
+        if !game_settings.bullet_limits.expired(age, bounces) {
+            continue;
+        }
+
+        // Without a collider the bullet can't hit anything and physics stops moving it
+        commands.entity(entity).remove::<Collider>().insert(Fading {
+            timer: Timer::from_seconds(BULLET_FADE_SECONDS, TimerMode::Once),
+        });
+    }
+}
+
+fn fade_bullets(
+    mut fading_query: Query<(Entity, &mut Fading, &mut Sprite)>,
+    fixed_time: Res<FixedTime>,
+    mut commands: Commands,
+) {
+    for (entity, mut fading, mut sprite) in &mut fading_query {
+        fading.timer.tick(fixed_time.period);
+        sprite.color.set_a(fading.timer.percent_left());
+
+        if fading.timer.finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Bullets that got used up this tick. Every system that uses up bullets checks here first,
+// so a bullet that got destroyed can't still hurt something later in the same tick.
+// They all get despawned together at the end of the bullet systems.
+#[derive(Resource, Default)]
+struct SpentBullets(HashSet<Entity>);
+
+impl SpentBullets {
+    fn contains(&self, bullet: Entity) -> bool {
+        self.0.contains(&bullet)
+    }
+
+    fn spend(&mut self, bullet: Entity) {
+        self.0.insert(bullet);
+    }
+}
+
+fn despawn_spent_bullets(mut spent_bullets: ResMut<SpentBullets>, mut commands: Commands) {
+    let mut bullets: Vec<Entity> = spent_bullets.0.drain().collect();
+
+    // Despawn in the same order every time so the entities get reused the same way in a replay
+    bullets.sort_unstable();
+
+    for bullet in bullets {
+        commands.entity(bullet).despawn();
+    }
+}
+
+// The pieces of a split bullet start out on top of each other,
+// so they remember what they split from to not destroy each other.
+#[derive(Component)]
+struct SplitFrom(Entity);
+
+// Splitting bullets break apart when they bounce, the pieces fan out around the direction it bounced in.
+// The bullet gets despawned when it splits, so it only ever splits on its first bounce.
+fn split_bullets(
+    mut collision_events: EventReader<CollisionEvent>,
+    bullet_query: Query<(&Transform, &Movement, &ProjectileKind, &Shooter)>,
+    bullet_sprite: Res<BulletSprite>,
+    mut spent_bullets: ResMut<SpentBullets>,
+    mut commands: Commands,
+) {
+    for collision_event in collision_events.iter() {
+        // A bullet can bounce off more than one thing in a tick, like in a corner
+        if collision_event.kind != ColliderType::Reflect
+            || spent_bullets.contains(collision_event.a)
+        {
+            continue;
+        }
+
+        let Ok((transform, movement, kind, shooter)) = bullet_query.get(collision_event.a) else {
+            continue;
+        };
+
+        let ProjectileEffect::Splitting { pieces } = kind.effect else {
+            continue;
+        };
+
+        let Some(direction) = movement.velocity.try_normalize() else {
+            continue;
+        };
+
+        spent_bullets.spend(collision_event.a);
+
+        for piece in 0..pieces {
+            let angle = (piece as f32 - (pieces - 1) as f32 / 2.0) * SPLIT_SPREAD;
+
+            let piece_entity = spawn_bullet(
+                &mut commands,
+                &bullet_sprite,
+                transform.translation,
+                Quat::from_rotation_z(angle) * direction,
+                ProjectileKind::SPLIT_PIECE,
+                *shooter,
+            );
+            commands
+                .entity(piece_entity)
+                .insert(SplitFrom(collision_event.a));
+        }
+    }
+}
+
+// Sent when two bullets hit each other and both get destroyed
+pub struct BulletAnnihilationEvent {
+    // Halfway between the two bullets
+    pub position: Vec3,
+}
+
+// When two bullets hit each other destroy both of them.
+// Both bullets get a collision event for the same hit, so only the one where `a` is the smaller entity counts.
+fn bullet_bullet_collisions(
+    mut collision_events: EventReader<CollisionEvent>,
+    bullet_query: Query<(&Transform, Option<&SplitFrom>), With<Bullet>>,
+    mut annihilation_events: EventWriter<BulletAnnihilationEvent>,
+    mut spent_bullets: ResMut<SpentBullets>,
+) {
+    for CollisionEvent { a, b, .. } in collision_events.iter() {
+        // A bullet can touch more than one other bullet in a tick but it can only be destroyed once
+        if a >= b || spent_bullets.contains(*a) || spent_bullets.contains(*b) {
+            continue;
+        }
+
+        if let (Ok((a_transform, a_split_from)), Ok((b_transform, b_split_from))) =
+            (bullet_query.get(*a), bullet_query.get(*b))
+        {
+            if let (Some(SplitFrom(a_parent)), Some(SplitFrom(b_parent))) =
+                (a_split_from, b_split_from)
+            {
+                if a_parent == b_parent {
+                    continue;
+                }
+            }
+
+            spent_bullets.spend(*a);
+            spent_bullets.spend(*b);
+
+            annihilation_events.send(BulletAnnihilationEvent {
+                position: a_transform.translation.lerp(b_transform.translation, 0.5),
+            });
+        }
+    }
+}
+
+// When the bullet hits anything with a hurtbox destroy the bullet and damage what it hit.
+// Piercing bullets keep going, and explosive bullets hurt every enemy around the one they hit.
+fn bullet_hurtbox_collisions(
+    mut collision_events: EventReader<CollisionEvent>,
+    hurtbox_query: Query<(&Hurtbox, &Transform)>,
+    mut bullet_query: Query<(
+        &Shooter,
+        &BulletAge,
+        &Bounces,
+        &ProjectileKind,
+        Option<&mut Pierced>,
+    )>,
+    friendly_fire: Res<FriendlyFire>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut explosion_events: EventWriter<ExplosionEvent>,
+    mut spent_bullets: ResMut<SpentBullets>,
+) {
+    for CollisionEvent { a, b, .. } in collision_events.iter() {
+        // A bullet can touch more than one thing in a tick, but it only gets used up once
+        if spent_bullets.contains(*a) {
+            continue;
+        }
+
+        let (Ok((shooter, age, bounces, kind, pierced)), Ok((hurtbox, hurtbox_transform))) =
+            (bullet_query.get_mut(*a), hurtbox_query.get(*b))
+        else {
+            continue;
+        };
+
+        if !friendly_fire.can_hit((shooter, age, bounces), *b, *hurtbox == Hurtbox::Enemy) {
+            continue;
+        }
+
+        // A piercing bullet can overlap the same thing for a few ticks on its way through
+        if let Some(mut pierced) = pierced {
+            if !pierced.0.insert(*b) {
+                continue;
+            }
+        }
+
+        match (hurtbox, kind.effect) {
+            // The player's health is their lives, so a big bullet still only takes one
+            (Hurtbox::Player, _) => damage_events.send(DamageEvent {
+                target: *b,
+                amount: PLAYER_BULLET_DAMAGE,
+                source: shooter.entity,
+            }),
+            (Hurtbox::Enemy, ProjectileEffect::Explosive { radius }) => {
+                explosion_events.send(ExplosionEvent {
+                    position: hurtbox_transform.translation,
+                    radius,
+                    damage: kind.damage,
+                    shooter: shooter.entity,
                 });
             }
+            (Hurtbox::Enemy, _) => damage_events.send(DamageEvent {
+                target: *b,
+                amount: kind.damage,
+                source: shooter.entity,
+            }),
+        }
+
+        if kind.effect != ProjectileEffect::Piercing {
+            spent_bullets.spend(*a);
         }
     }
 }
 
-// When the bullet hits the player destroy both the
-// bullet and the player.
-fn bullet_player_collisions(
-    player_query: Query<(&Transform, Entity), With<Player>>,
-    bullet_query: Query<(&Transform, Entity), (With<Bullet>, Without<Player>)>,
-    mut commands: Commands,
+// Sent when an explosive bullet hits an enemy
+pub struct ExplosionEvent {
+    pub position: Vec3,
+    pub radius: f32,
+    pub damage: u32,
+    // Whoever shot the explosive bullet
+    pub shooter: Entity,
+}
+
+// Explosions damage every enemy in range, including the one that got hit
+fn explode(
+    mut explosion_events: EventReader<ExplosionEvent>,
+    hurtbox_query: Query<(Entity, &Hurtbox, &Transform)>,
+    mut damage_events: EventWriter<DamageEvent>,
 ) {
-    let (player_transform, player_entity) = player_query
-        .get_single()
-        .expect("Could not find single player");
-
-    for (bullet_transform, bullet_entity) in &bullet_query {
-        if let Some(_collision) = collide(
-            bullet_transform.translation,
-            Vec2::new(BULLET_SIZE, BULLET_SIZE),
-            player_transform.translation,
-            Vec2::new(PLAYER_SIZE, PLAYER_SIZE),
-        ) {
-            commands.entity(player_entity).despawn();
-            commands.entity(bullet_entity).despawn();
+    for explosion_event in explosion_events.iter() {
+        for (entity, hurtbox, transform) in &hurtbox_query {
+            if *hurtbox != Hurtbox::Enemy
+                || transform
+                    .translation
+                    .truncate()
+                    .distance(explosion_event.position.truncate())
+                    > explosion_event.radius
+            {
+                continue;
+            }
+
+            damage_events.send(DamageEvent {
+                target: entity,
+                amount: explosion_event.damage,
+                source: explosion_event.shooter,
+            });
         }
     }
 }
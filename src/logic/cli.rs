@@ -0,0 +1,20 @@
+// Finds the value passed to a command line flag, either as `--flag value` or `--flag=value`
+pub fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args();
+
+    while let Some(arg) = args.next() {
+        let Some(rest) = arg.strip_prefix(flag) else {
+            continue;
+        };
+
+        if rest.is_empty() {
+            return args.next();
+        }
+
+        if let Some(value) = rest.strip_prefix('=') {
+            return Some(value.to_string());
+        }
+    }
+
+    None
+}
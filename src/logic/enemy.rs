@@ -1,8 +1,14 @@
-use crate::logic::physics::{ColliderType, Movement, ShootingEvent};
-use crate::logic::player::{Player, PLAYER_SIZE};
+use crate::logic::bullet::{ProjectileKind, ShootingEvent, ShootingSet};
+use crate::logic::health::{DamageEvent, DamageSet, DeathEvent, DeathSet, Health, Hurtbox};
+use crate::logic::physics::{
+    CastFilter, Collider, ColliderType, CollisionEvent, CollisionLayers, CollisionTag, Movement,
+    PhysicsQuery, PhysicsSet,
+};
+use crate::logic::player::{Player, PlayerSet};
+use crate::logic::rng::GameRng;
+use crate::logic::state::{despawn_all, GameState, NewGame};
 use crate::{EnemySprite, Flashing, TurretSprite, WINDOWHEIGHT, WINDOWWIDTH};
 use bevy::prelude::*;
-use bevy::sprite::collide_aabb::collide;
 use rand::Rng;
 use std::time::Duration;
 
@@ -12,20 +18,55 @@ pub struct EnemyPlugin;
 // const SHOOTING_ENEMY_SPAWN_TIMESTEP: f64 = 7.0;
 // const SHOOTING_ENEMY_SHOOT_TIMESTEP: f64 = 2.0;
 pub const ENEMY_SIZE: f32 = 40.0;
+// Turrets take a few hits to kill
+const NORMAL_ENEMY_HEALTH: u32 = 1;
+const SHOOTING_ENEMY_HEALTH: u32 = 3;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup_enemy_spawning)
-            .add_system(tick_timers)
-            .add_system(spawn_normal_enemy.run_if(normal_enemy_spawn_system))
-            .add_system(spawn_shooting_enemy.run_if(shooting_enemy_spawn_system))
-            .add_system(shooting_enemy_shooting)
-            .add_system(move_normal_enemies)
-            .add_event::<EnemyDeathEvent>()
-            .add_system(normal_enemy_player_collisions)
-            .add_system(shooting_enemy_player_collisions)
-            .add_system(rotate_to_face_player)
-            .add_system(spawn_enemies);
+        app.add_systems_to_schedule(
+            NewGame,
+            (
+                setup_enemy_spawning,
+                despawn_all::<NormalSpawnTimer>,
+                despawn_all::<EnemySpawn>,
+                despawn_all::<Enemy>,
+                despawn_all::<ShootingEnemy>,
+            ),
+        )
+        // Everything that runs on a timer or steers enemies runs in the fixed schedule,
+        // so it lines up with physics no matter what the frame rate is
+        .add_systems_to_schedule(
+            CoreSchedule::FixedUpdate,
+            (
+                tick_timers,
+                spawn_normal_enemy.run_if(normal_enemy_spawn_system),
+                spawn_shooting_enemy.run_if(shooting_enemy_spawn_system),
+                spawn_enemies,
+                // Turrets shoot the way they face, so this has to be in step with the shooting
+                rotate_to_face_player,
+                shooting_enemy_shooting.in_set(ShootingSet),
+                move_normal_enemies,
+            )
+                .chain()
+                .after(PlayerSet)
+                .before(PhysicsSet)
+                .distributive_run_if(in_state(GameState::Playing)),
+        )
+        .add_event::<EnemyDeathEvent>()
+        .add_system_to_schedule(
+            CoreSchedule::FixedUpdate,
+            enemy_player_collisions
+                .in_set(DamageSet)
+                .after(PhysicsSet)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_system_to_schedule(
+            CoreSchedule::FixedUpdate,
+            enemy_deaths
+                .in_set(DeathSet)
+                .run_if(in_state(GameState::Playing)),
+        );
     }
 }
 
@@ -47,12 +88,12 @@ struct ShootingSpawnTimer {
 
 // TODO idk this is odd
 fn tick_timers(
-    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
     mut query: Query<(&mut NormalSpawnTimer, &mut ShootingSpawnTimer)>,
 ) {
     for (mut normal_timer, mut shooting_timer) in &mut query {
-        normal_timer.timer.tick(time.delta());
-        shooting_timer.timer.tick(time.delta());
+        normal_timer.timer.tick(fixed_time.period);
+        shooting_timer.timer.tick(fixed_time.period);
     }
 }
 // on startup, setup enemy spawning
@@ -85,6 +126,9 @@ fn shooting_enemy_spawn_system(query: Query<&ShootingSpawnTimer>) -> bool {
 #[derive(Component)]
 pub struct Enemy;
 
+// Filters a query down to every kind of enemy
+pub type AnyEnemy = Or<(With<Enemy>, With<ShootingEnemy>)>;
+
 #[derive(Component)]
 pub struct ShootingEnemy {
     // Timer for how long it takes for an enemy to shoot
@@ -105,25 +149,38 @@ enum EnemyType {
     NormalEnemy,
 }
 
-fn spawn_shooting_enemy(mut commands: Commands, turret_sprite: Res<TurretSprite>) {
+fn spawn_shooting_enemy(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    turret_sprite: Res<TurretSprite>,
+) {
     spawn_enemy_location(
         &mut commands,
+        &mut rng,
         EnemyType::ShootingEnemy,
         turret_sprite.0.clone(),
     );
 }
 
-fn spawn_normal_enemy(mut commands: Commands, enemy_sprite: Res<EnemySprite>) {
+fn spawn_normal_enemy(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    enemy_sprite: Res<EnemySprite>,
+) {
     spawn_enemy_location(
         &mut commands,
+        &mut rng,
         EnemyType::NormalEnemy,
         enemy_sprite.0.clone(),
     );
 }
 
-fn spawn_enemy_location(commands: &mut Commands, enemy_type: EnemyType, sprite: Handle<Image>) {
-    let mut rng = rand::thread_rng();
-
+fn spawn_enemy_location(
+    commands: &mut Commands,
+    rng: &mut GameRng,
+    enemy_type: EnemyType,
+    sprite: Handle<Image>,
+) {
     let (x, y) = (
         WINDOWWIDTH / 2.0 - rng.gen_range(40.0..=WINDOWWIDTH - 40.0),
         WINDOWHEIGHT / 2.0 - rng.gen_range(40.0..=WINDOWHEIGHT - 40.0),
@@ -148,7 +205,14 @@ fn spawn_enemy_location(commands: &mut Commands, enemy_type: EnemyType, sprite:
             flashed: false,
             timer: Timer::new(Duration::from_secs_f32(0.5), TimerMode::Repeating),
         })
-        .insert(ColliderType::Stop);
+        .insert(Collider::aabb(Vec2::new(ENEMY_SIZE, ENEMY_SIZE)))
+        .insert(ColliderType::Stop)
+        .insert(CollisionTag::EnemySpawn)
+        // Enemies walk right over where other enemies are spawning in
+        .insert(CollisionLayers::new(
+            CollisionLayers::ENEMY_SPAWN,
+            CollisionLayers::PLAYER | CollisionLayers::BULLET,
+        ));
 }
 
 // Spawn enemies once the enemy spawn timer is up
@@ -157,10 +221,10 @@ fn spawn_enemies(
     mut commands: Commands,
     enemy_sprite: Res<EnemySprite>,
     turret_sprite: Res<TurretSprite>,
-    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
 ) {
     for (entity, transform, mut enemy_spawn) in &mut enemy_spawn_query {
-        enemy_spawn.spawn_timer.tick(time.delta());
+        enemy_spawn.spawn_timer.tick(fixed_time.period);
 
         if enemy_spawn.spawn_timer.finished() {
             commands.entity(entity).despawn();
@@ -178,9 +242,18 @@ fn spawn_enemies(
                             ..Default::default()
                         })
                         .insert(Enemy)
+                        .insert(Health::new(NORMAL_ENEMY_HEALTH))
+                        .insert(Hurtbox::Enemy)
                         .insert(Movement {
                             velocity: Vec3::new(0.0, 0.0, 0.0),
                         })
+                        .insert(Collider::aabb(Vec2::new(ENEMY_SIZE, ENEMY_SIZE)))
+                        .insert(ColliderType::Stop)
+                        .insert(CollisionTag::Enemy)
+                        .insert(CollisionLayers::new(
+                            CollisionLayers::ENEMY,
+                            CollisionLayers::ALL,
+                        ))
                         .insert(FacingPlayer);
                 }
                 EnemyType::ShootingEnemy => {
@@ -200,6 +273,15 @@ fn spawn_enemies(
                                 TimerMode::Repeating,
                             ),
                         })
+                        .insert(Health::new(SHOOTING_ENEMY_HEALTH))
+                        .insert(Hurtbox::Enemy)
+                        .insert(Collider::circle(ENEMY_SIZE / 2.0))
+                        .insert(ColliderType::Stop)
+                        .insert(CollisionTag::Enemy)
+                        .insert(CollisionLayers::new(
+                            CollisionLayers::ENEMY,
+                            CollisionLayers::ALL,
+                        ))
                         .insert(FacingPlayer);
                 }
             }
@@ -209,6 +291,8 @@ fn spawn_enemies(
 
 pub struct EnemyDeathEvent {
     pub death_position: Vec3,
+    // Whatever dealt the final hit, like whoever shot the bullet
+    pub killer: Entity,
 }
 
 // Enemies follow the player.
@@ -216,14 +300,20 @@ fn move_normal_enemies(
     mut enemy_query: Query<(&Transform, &mut Movement), (With<Enemy>, Without<Player>)>,
     player_query: Query<&Transform, (With<Player>, Without<Enemy>)>,
 ) {
-    let player_transform = player_query
-        .get_single()
-        .expect("Could not find a single player.");
+    // Enemies stop chasing once the player dies since this only runs while Playing
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
 
     for (enemy_transform, mut movement) in &mut enemy_query {
         let vector_to_player = player_transform.translation;
         let vector_to_enemy = enemy_transform.translation;
-        let direction_from_enemy_to_player: Vec3 = (vector_to_player - vector_to_enemy).normalize();
+        // An enemy sitting right on top of the player has no direction to go in
+        let Some(direction_from_enemy_to_player) =
+            (vector_to_player - vector_to_enemy).try_normalize()
+        else {
+            continue;
+        };
 
         // This works by changing the velocity of the enemy every tick
         // to be in the direction of the player
@@ -232,16 +322,44 @@ fn move_normal_enemies(
     }
 }
 
-// Shooting enemies shoot at the player
+// Shooting enemies shoot at the player, as long as they can see them
 fn shooting_enemy_shooting(
     mut event_writer: EventWriter<ShootingEvent>,
-    mut query: Query<(Entity, &mut ShootingEnemy)>,
-    time: Res<Time>,
+    mut query: Query<(Entity, &Transform, &mut ShootingEnemy)>,
+    player_query: Query<(Entity, &Transform), With<Player>>,
+    physics_query: PhysicsQuery,
+    fixed_time: Res<FixedTime>,
 ) {
+    let player = player_query.get_single().ok();
+
     // TODO stop using events
-    for (shooting_enemy_entity, mut shooting_enemy) in &mut query {
-        if shooting_enemy.shooting_timer.tick(time.delta()).finished() {
-            event_writer.send(ShootingEvent(shooting_enemy_entity));
+    for (shooting_enemy_entity, transform, mut shooting_enemy) in &mut query {
+        if !shooting_enemy
+            .shooting_timer
+            .tick(fixed_time.period)
+            .finished()
+        {
+            continue;
+        }
+
+        let Some((player_entity, player_transform)) = player else {
+            continue;
+        };
+
+        // Turrets skip their shot when a wall is in the way
+        let vector_to_player = (player_transform.translation - transform.translation).truncate();
+        let first_hit = physics_query.cast_ray(
+            transform.translation.truncate(),
+            vector_to_player,
+            vector_to_player.length(),
+            &CastFilter::new(CollisionLayers::WALL | CollisionLayers::PLAYER),
+        );
+
+        if first_hit.map(|hit| hit.entity) == Some(player_entity) {
+            event_writer.send(ShootingEvent {
+                shooter: shooting_enemy_entity,
+                kind: ProjectileKind::NORMAL,
+            });
         }
     }
 }
@@ -253,72 +371,56 @@ fn rotate_to_face_player(
     mut facing_player_query: Query<&mut Transform, With<FacingPlayer>>,
     player_query: Query<&Transform, (With<Player>, Without<FacingPlayer>)>,
 ) {
-    let player_transform = player_query
-        .get_single()
-        .expect("Could not find single player");
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
 
     for mut facing_transform in &mut facing_player_query {
-        let direction_to_player =
-            (player_transform.translation - facing_transform.translation).normalize();
+        let Some(direction_to_player) =
+            (player_transform.translation - facing_transform.translation).try_normalize()
+        else {
+            continue;
+        };
 
         let rotate_to_player = Quat::from_rotation_arc(Vec3::Y, direction_to_player);
         facing_transform.rotation = rotate_to_player;
     }
 }
 
-// I don't like how I have two identical systems :(
-// Enemies kill the player if they touch them
-fn normal_enemy_player_collisions(
-    enemy_query: Query<&Transform, With<Enemy>>,
-    player_query: Query<(&Transform, Entity), (With<Player>, Without<Enemy>)>,
-    mut commands: Commands,
+// Enemies hurt the player if they touch them.
+// The player always has a collision response to touching an enemy,
+// so only the events where the player is `a` need to be checked.
+fn enemy_player_collisions(
+    mut collision_events: EventReader<CollisionEvent>,
+    enemy_query: Query<(), AnyEnemy>,
+    player_query: Query<(), With<Player>>,
+    mut damage_events: EventWriter<DamageEvent>,
 ) {
-    let (player_transform, player_entity) = player_query
-        .get_single()
-        .expect("Could not find single player");
-
-    for enemy_transform in &enemy_query {
-        enemy_player_collisions(
-            &mut commands,
-            &enemy_transform.translation,
-            &player_transform.translation,
-            player_entity,
-        );
+    for CollisionEvent { a, b, .. } in collision_events.iter() {
+        if player_query.contains(*a) && enemy_query.contains(*b) {
+            damage_events.send(DamageEvent {
+                target: *a,
+                amount: 1,
+                source: *b,
+            });
+        }
     }
 }
 
-// Enemies kill the player if they touch them
-fn shooting_enemy_player_collisions(
-    enemy_query: Query<&Transform, With<ShootingEnemy>>,
-    player_query: Query<(&Transform, Entity), (With<Player>, Without<ShootingEnemy>)>,
+// Enemies that run out of health die
+fn enemy_deaths(
+    mut death_events: EventReader<DeathEvent>,
+    enemy_query: Query<(), AnyEnemy>,
+    mut enemy_death_events: EventWriter<EnemyDeathEvent>,
     mut commands: Commands,
 ) {
-    let (player_transform, player_entity) = player_query
-        .get_single()
-        .expect("Could not find single player");
-
-    for enemy_transform in &enemy_query {
-        enemy_player_collisions(
-            &mut commands,
-            &enemy_transform.translation,
-            &player_transform.translation,
-            player_entity,
-        );
-    }
-}
-
-fn enemy_player_collisions(
-    commands: &mut Commands,
-    enemy_translation: &Vec3,
-    player_translation: &Vec3,
-    player_entity: Entity,
-) {
-    if let Some(_collision) = collide(
-        *enemy_translation,
-        Vec2::new(ENEMY_SIZE, ENEMY_SIZE),
-        *player_translation,
-        Vec2::new(PLAYER_SIZE, PLAYER_SIZE),
-    ) {
-        commands.entity(player_entity).despawn();
+    for death_event in death_events.iter() {
+        if enemy_query.contains(death_event.entity) {
+            commands.entity(death_event.entity).despawn();
+            enemy_death_events.send(EnemyDeathEvent {
+                death_position: death_event.position,
+                killer: death_event.killer,
+            });
+        }
     }
 }
@@ -0,0 +1,159 @@
+use crate::logic::state::GameState;
+use crate::Flashing;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use std::time::Duration;
+
+pub struct HealthPlugin;
+
+// Systems that send DamageEvents run in this set in the fixed schedule.
+#[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
+pub struct DamageSet;
+
+// Systems that react to DeathEvents run in this set in the fixed schedule,
+// anything that cares about what died runs after it.
+#[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
+pub struct DeathSet;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .add_event::<DeathEvent>()
+            .add_systems_to_schedule(
+                CoreSchedule::FixedUpdate,
+                (tick_invulnerability, apply_damage)
+                    .chain()
+                    .after(DamageSet)
+                    .before(DeathSet)
+                    .distributive_run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct Health {
+    pub current: u32,
+    // How long the entity can't be hurt again after taking a hit
+    pub invulnerability: Duration,
+}
+
+impl Health {
+    pub fn new(health: u32) -> Self {
+        Health {
+            current: health,
+            invulnerability: Duration::ZERO,
+        }
+    }
+
+    pub fn with_invulnerability(mut self, invulnerability: Duration) -> Self {
+        self.invulnerability = invulnerability;
+        self
+    }
+}
+
+// Something that bullets can hurt, and which side it's on
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hurtbox {
+    Player,
+    Enemy,
+}
+
+// Sent whenever something should lose health
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: u32,
+    // Whatever caused the damage, like whoever shot the bullet
+    pub source: Entity,
+}
+
+// Sent when something runs out of health. Nothing gets despawned,
+// it's up to whatever owns the entity to decide what dying means.
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub position: Vec3,
+    // The source of the damage that finished it off
+    pub killer: Entity,
+}
+
+// The entity can't lose health until the timer runs out
+#[derive(Component)]
+struct Invulnerable {
+    timer: Timer,
+}
+
+// Takes health away for every hit. Entities with invulnerability
+// flash for a while afterwards, and ignore any other hits until it wears off.
+fn apply_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut health_query: Query<(&mut Health, &Transform), Without<Invulnerable>>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut commands: Commands,
+) {
+    // Invulnerable doesn't show up in the query until the commands are applied,
+    // so this keeps anything that became invulnerable this tick from getting hit again.
+    let mut invulnerable_entities = HashSet::default();
+
+    for DamageEvent {
+        target,
+        amount,
+        source,
+    } in damage_events.iter()
+    {
+        if invulnerable_entities.contains(target) {
+            continue;
+        }
+
+        let Ok((mut health, transform)) = health_query.get_mut(*target) else {
+            continue;
+        };
+
+        // Already dead, whoever owns it just hasn't gotten rid of it yet
+        if health.current == 0 {
+            continue;
+        }
+
+        health.current = health.current.saturating_sub(*amount);
+
+        if health.current == 0 {
+            death_events.send(DeathEvent {
+                entity: *target,
+                position: transform.translation,
+                killer: *source,
+            });
+            continue;
+        }
+
+        if health.invulnerability.is_zero() {
+            continue;
+        }
+
+        invulnerable_entities.insert(*target);
+        commands
+            .entity(*target)
+            .insert(Invulnerable {
+                timer: Timer::new(health.invulnerability, TimerMode::Once),
+            })
+            .insert(Flashing {
+                flashed: false,
+                timer: Timer::new(Duration::from_secs_f32(0.1), TimerMode::Repeating),
+            });
+    }
+}
+
+fn tick_invulnerability(
+    mut invulnerable_query: Query<(Entity, &mut Invulnerable, &mut Sprite)>,
+    fixed_time: Res<FixedTime>,
+    mut commands: Commands,
+) {
+    for (entity, mut invulnerable, mut sprite) in &mut invulnerable_query {
+        if invulnerable.timer.tick(fixed_time.period).finished() {
+            commands
+                .entity(entity)
+                .remove::<Invulnerable>()
+                .remove::<Flashing>();
+
+            // Flashing leaves the sprite tinted
+            sprite.color = Color::WHITE;
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use crate::logic::bullet::{BulletLimits, FriendlyFire};
+use crate::logic::cli::arg_value;
+use crate::logic::menu::GameSettings;
+use crate::logic::physics::PhysicsSet;
+use crate::logic::player::Player;
+use crate::logic::rng::GameRng;
+use crate::logic::state::{GameState, NewGame};
+use crate::{WINDOWHEIGHT, WINDOWWIDTH};
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::str::Lines;
+use std::time::Duration;
+
+pub struct PlayerInputPlugin;
+
+// Goes up every time the recording format changes, older recordings can't be played back
+const RECORDING_VERSION: u32 = 1;
+
+// Gameplay systems that read the player's input run after this set in the fixed schedule.
+#[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
+pub struct InputSet;
+
+impl Plugin for PlayerInputPlugin {
+    fn build(&self, app: &mut App) {
+        // Pass `--record <file>` to save every tick's input, and `--replay <file>` to play it back.
+        // A replay also uses the seed and settings it was recorded with,
+        // so this has to be added after the RngPlugin and the MenuPlugin.
+        let input_source = match (arg_value("--record"), arg_value("--replay")) {
+            (_, Some(path)) => {
+                let recording = read_recording(&path);
+                info!("Replaying {path}");
+
+                app.insert_resource(GameRng::new(recording.seed))
+                    .insert_resource(recording.game_settings)
+                    .insert_resource(recording.friendly_fire);
+                InputSource::Playback(recording.inputs)
+            }
+            (Some(path), None) => {
+                let file =
+                    LineWriter::new(File::create(&path).expect("Could not create the recording"));
+                info!("Recording to {path}");
+
+                InputSource::Recording(file)
+            }
+            (None, None) => InputSource::Live,
+        };
+
+        app.insert_resource(input_source)
+            .init_resource::<LiveInput>()
+            .init_resource::<PlayerInput>()
+            // There is no keyboard or mouse when running headless
+            .add_system(
+                gather_live_input
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem)
+                    .run_if(resource_exists::<Input<KeyCode>>())
+                    .run_if(resource_exists::<Input<MouseButton>>()),
+            )
+            .add_system_to_schedule(OnEnter(GameState::Playing), clear_live_input)
+            .add_system_to_schedule(NewGame, write_recording_header)
+            .add_system_to_schedule(
+                CoreSchedule::FixedUpdate,
+                sample_player_input
+                    .in_set(InputSet)
+                    .before(PhysicsSet)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+// What the player is doing this tick. This is all the player systems get to see,
+// so a recording of these plays back exactly the same game.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq)]
+pub struct PlayerInput {
+    // Each axis is -1, 0 or 1
+    pub movement: Vec2,
+    pub fire: bool,
+    pub switch_weapon: bool,
+    // The direction from the player to the cursor, if the cursor is in the window
+    pub aim: Option<Vec2>,
+}
+
+// Where the input for each tick comes from
+#[derive(Resource)]
+pub enum InputSource {
+    Live,
+    // Live input that also gets written to a file, a line per tick
+    Recording(LineWriter<File>),
+    Playback(VecDeque<PlayerInput>),
+}
+
+// The keyboard and mouse as of the latest frame.
+// Frames and ticks don't line up, so presses get held on to until a tick uses them.
+#[derive(Resource, Default)]
+struct LiveInput {
+    movement: Vec2,
+    fire: bool,
+    switch_weapon: bool,
+    // In world space
+    cursor: Option<Vec2>,
+}
+
+// Move with WASD or the arrow keys, shoot with space or left click, aim with the cursor
+// and switch weapons with Q or right click
+fn gather_live_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window>,
+    mut live_input: ResMut<LiveInput>,
+) {
+    let up = keyboard_input.pressed(KeyCode::Up) || keyboard_input.pressed(KeyCode::W);
+    let down = keyboard_input.pressed(KeyCode::Down) || keyboard_input.pressed(KeyCode::S);
+    let left = keyboard_input.pressed(KeyCode::Left) || keyboard_input.pressed(KeyCode::A);
+    let right = keyboard_input.pressed(KeyCode::Right) || keyboard_input.pressed(KeyCode::D);
+
+    // If left is pressed than it will be -1, right 1, both they cancel out.
+    let x_axis: i8 = -(left as i8) + right as i8;
+    let y_axis: i8 = -(down as i8) + up as i8;
+    live_input.movement = Vec2::new(x_axis as f32, y_axis as f32);
+
+    live_input.fire |=
+        keyboard_input.just_pressed(KeyCode::Space) || buttons.just_pressed(MouseButton::Left);
+    live_input.switch_weapon |=
+        keyboard_input.just_pressed(KeyCode::Q) || buttons.just_pressed(MouseButton::Right);
+
+    // The position of the cursor is given from (0,0) in the top left to (screen width, screen height) in the bottom right.
+    // Most bevy coordinates are done with (0,0) in the middle of the screen.
+    // This translates from screen space to world space.
+    live_input.cursor = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .map(|position| position + Vec2::new(-WINDOWWIDTH / 2.0, -WINDOWHEIGHT / 2.0));
+}
+
+// The click on Start or Resume got held on to like any other press,
+// so forget it or the player would shoot as soon as the game starts
+fn clear_live_input(mut live_input: ResMut<LiveInput>) {
+    live_input.fire = false;
+    live_input.switch_weapon = false;
+}
+
+fn sample_player_input(
+    mut input_source: ResMut<InputSource>,
+    mut live_input: ResMut<LiveInput>,
+    mut player_input: ResMut<PlayerInput>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let live = || PlayerInput {
+        movement: live_input.movement,
+        fire: live_input.fire,
+        switch_weapon: live_input.switch_weapon,
+        aim: live_input
+            .cursor
+            .zip(player_query.get_single().ok())
+            .and_then(|(cursor, player_transform)| {
+                (cursor - player_transform.translation.truncate()).try_normalize()
+            }),
+    };
+
+    *player_input = match &mut *input_source {
+        InputSource::Live => live(),
+        InputSource::Recording(file) => {
+            let input = live();
+            writeln!(file, "{}", format_input(&input)).expect("Could not write to the recording");
+            input
+        }
+        InputSource::Playback(inputs) => match inputs.pop_front() {
+            Some(input) => input,
+            // Hand control back to the player once the recording runs out
+            None => {
+                info!("Replay finished");
+                *input_source = InputSource::Live;
+                live()
+            }
+        },
+    };
+
+    live_input.fire = false;
+    live_input.switch_weapon = false;
+}
+
+// The settings can still be changed from the menu after the recording starts,
+// so the header gets written once the first game actually starts.
+fn write_recording_header(
+    mut input_source: ResMut<InputSource>,
+    rng: Res<GameRng>,
+    game_settings: Res<GameSettings>,
+    friendly_fire: Res<FriendlyFire>,
+    mut recorded_header: Local<Option<String>>,
+) {
+    let InputSource::Recording(file) = &mut *input_source else {
+        return;
+    };
+
+    let header = format_header(rng.seed(), &game_settings, &friendly_fire);
+
+    match &*recorded_header {
+        None => {
+            write!(file, "{header}").expect("Could not write to the recording");
+            *recorded_header = Some(header);
+        }
+        // Every game after the first keeps going in the same recording
+        Some(recorded_header) if *recorded_header != header => {
+            warn!("The settings changed since the recording started, so it won't replay right")
+        }
+        Some(_) => {}
+    }
+}
+
+struct Recording {
+    seed: u64,
+    game_settings: GameSettings,
+    friendly_fire: FriendlyFire,
+    inputs: VecDeque<PlayerInput>,
+}
+
+// The header has a line for the format version, the seed, and one for each setting that changes how the game plays.
+// Times are in milliseconds and "-" means there is no limit.
+fn format_header(seed: u64, game_settings: &GameSettings, friendly_fire: &FriendlyFire) -> String {
+    let BulletLimits {
+        lifetime,
+        max_bounces,
+    } = game_settings.bullet_limits;
+
+    format!(
+        "version {RECORDING_VERSION}\nseed {seed}\nstarting_ammo {}\nbullet_limits {} {}\nfriendly_fire {} {} {}\n",
+        game_settings.starting_ammo,
+        format_limit(lifetime.map(|lifetime| lifetime.as_millis())),
+        format_limit(max_bounces),
+        friendly_fire.shooter_grace_period.as_millis(),
+        friendly_fire.shooter_grace_bounces,
+        friendly_fire.enemy_bullets_hit_enemies as u8,
+    )
+}
+
+fn format_limit<T: ToString>(limit: Option<T>) -> String {
+    limit.map_or("-".to_string(), |limit| limit.to_string())
+}
+
+fn parse_limit<T: std::str::FromStr>(limit: &str) -> Option<Option<T>> {
+    match limit {
+        "-" => Some(None),
+        limit => limit.parse().ok().map(Some),
+    }
+}
+
+// Each line after the header is one tick: "x y fire switch_weapon"
+// followed by "aim_x aim_y" or "-" for no aim.
+fn format_input(input: &PlayerInput) -> String {
+    let aim = match input.aim {
+        Some(aim) => format!("{} {}", aim.x, aim.y),
+        None => "-".to_string(),
+    };
+
+    format!(
+        "{} {} {} {} {}",
+        input.movement.x, input.movement.y, input.fire as u8, input.switch_weapon as u8, aim
+    )
+}
+
+fn read_recording(path: &str) -> Recording {
+    let contents = std::fs::read_to_string(path).expect("Could not read the recording");
+    let mut lines = contents.lines();
+
+    // Recordings from before there was a version don't have the line at all
+    let version = lines
+        .next()
+        .and_then(|line| line.strip_prefix("version "))
+        .and_then(|version| version.parse::<u32>().ok())
+        .unwrap_or(0);
+
+    if version != RECORDING_VERSION {
+        panic!("Can't play back a version {version} recording, only version {RECORDING_VERSION}");
+    }
+
+    let seed = header_line(&mut lines, "seed")
+        .parse()
+        .expect("Bad seed in the recording");
+    let starting_ammo = header_line(&mut lines, "starting_ammo")
+        .parse()
+        .expect("Bad starting ammo in the recording");
+    let bullet_limits = parse_bullet_limits(header_line(&mut lines, "bullet_limits"))
+        .expect("Bad bullet limits in the recording");
+    let friendly_fire = parse_friendly_fire(header_line(&mut lines, "friendly_fire"))
+        .expect("Bad friendly fire settings in the recording");
+
+    let inputs = lines
+        .map(|line| parse_input(line).unwrap_or_else(|| panic!("Bad line in recording: {line}")))
+        .collect();
+
+    Recording {
+        seed,
+        game_settings: GameSettings {
+            starting_ammo,
+            bullet_limits,
+        },
+        friendly_fire,
+        inputs,
+    }
+}
+
+// The rest of the next line, which has to start with `name`
+fn header_line<'a>(lines: &mut Lines<'a>, name: &str) -> &'a str {
+    lines
+        .next()
+        .and_then(|line| line.strip_prefix(name))
+        .and_then(|line| line.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("The recording is missing its {name}"))
+}
+
+fn parse_bullet_limits(line: &str) -> Option<BulletLimits> {
+    let [lifetime, max_bounces] = line.split(' ').collect::<Vec<&str>>()[..] else {
+        return None;
+    };
+
+    Some(BulletLimits {
+        lifetime: parse_limit(lifetime)?.map(Duration::from_millis),
+        max_bounces: parse_limit(max_bounces)?,
+    })
+}
+
+fn parse_friendly_fire(line: &str) -> Option<FriendlyFire> {
+    let [grace_period, grace_bounces, enemy_bullets_hit_enemies] =
+        line.split(' ').collect::<Vec<&str>>()[..]
+    else {
+        return None;
+    };
+
+    Some(FriendlyFire {
+        shooter_grace_period: Duration::from_millis(grace_period.parse().ok()?),
+        shooter_grace_bounces: grace_bounces.parse().ok()?,
+        enemy_bullets_hit_enemies: enemy_bullets_hit_enemies == "1",
+    })
+}
+
+fn parse_input(line: &str) -> Option<PlayerInput> {
+    let values: Vec<&str> = line.split(' ').collect();
+
+    let [x, y, fire, switch_weapon, ref aim @ ..] = values[..] else {
+        return None;
+    };
+
+    let aim = match aim {
+        ["-"] => None,
+        [aim_x, aim_y] => Some(Vec2::new(aim_x.parse().ok()?, aim_y.parse().ok()?)),
+        _ => return None,
+    };
+
+    Some(PlayerInput {
+        movement: Vec2::new(x.parse().ok()?, y.parse().ok()?),
+        fire: fire == "1",
+        switch_weapon: switch_weapon == "1",
+        aim,
+    })
+}
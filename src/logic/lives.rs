@@ -0,0 +1,94 @@
+use crate::logic::health::{DeathEvent, DeathSet, Health};
+use crate::logic::player::Player;
+use crate::logic::state::GameState;
+use crate::UiFont;
+use bevy::prelude::*;
+use std::time::Duration;
+
+pub struct LivesPlugin;
+
+// The player's lives are their health, each hit takes one
+pub const STARTING_LIVES: u32 = 3;
+// How long the player can't be hurt after getting hit
+pub const PLAYER_INVULNERABILITY: Duration = Duration::from_secs(2);
+
+impl Plugin for LivesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_schedule(
+            CoreSchedule::FixedUpdate,
+            player_death
+                .in_set(DeathSet)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_startup_system(show_lives_ui)
+        .add_system(update_lives_ui);
+    }
+}
+
+// The game is over once the player runs out of lives
+fn player_death(
+    mut death_events: EventReader<DeathEvent>,
+    player_query: Query<(), With<Player>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for death_event in death_events.iter() {
+        if player_query.contains(death_event.entity) {
+            next_state.set(GameState::GameOver);
+        }
+    }
+}
+
+#[derive(Component)]
+struct LivesUI;
+
+// Lives are shown in the top right
+fn show_lives_ui(mut commands: Commands, ui_font: Res<UiFont>) {
+    let lives_text: Text = Text::from_sections([
+        TextSection::new(
+            "Lives: ",
+            TextStyle {
+                font: ui_font.0.clone(),
+                font_size: 60.0,
+                color: Color::WHITE,
+            },
+        ),
+        TextSection::new(
+            STARTING_LIVES.to_string(),
+            TextStyle {
+                font: ui_font.0.clone(),
+                font_size: 60.0,
+                color: Color::CRIMSON,
+            },
+        ),
+    ]);
+
+    commands
+        .spawn(TextBundle {
+            text: lives_text,
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(50.0),
+                    right: Val::Px(50.0),
+                    ..default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(LivesUI);
+}
+
+// Update the lives ui whenever the player's lives change
+fn update_lives_ui(
+    mut lives_ui_query: Query<&mut Text, With<LivesUI>>,
+    health_query: Query<&Health, (With<Player>, Changed<Health>)>,
+) {
+    let mut lives_ui = lives_ui_query
+        .get_single_mut()
+        .expect("Could not find the lives ui");
+
+    for health in &health_query {
+        lives_ui.sections[1].value = health.current.to_string();
+    }
+}
@@ -0,0 +1,258 @@
+use crate::logic::bullet::BulletLimits;
+use crate::logic::player::STARTING_AMMO;
+use crate::logic::state::{despawn_all, GameState};
+use crate::UiFont;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use std::time::Duration;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameSettings>()
+            .add_system_to_schedule(OnEnter(GameState::MainMenu), show_main_menu)
+            .add_system_to_schedule(OnExit(GameState::MainMenu), despawn_all::<MainMenuScreen>)
+            .add_system_to_schedule(OnEnter(GameState::Settings), show_settings)
+            .add_system_to_schedule(OnExit(GameState::Settings), despawn_all::<SettingsScreen>)
+            .add_system_to_schedule(OnEnter(GameState::Paused), show_pause_screen)
+            .add_system_to_schedule(OnExit(GameState::Paused), despawn_all::<PauseScreen>)
+            .add_system(press_menu_buttons)
+            .add_system(update_setting_labels);
+    }
+}
+
+// Settings picked from the settings screen, they get used the next time a game starts
+#[derive(Resource)]
+pub struct GameSettings {
+    pub starting_ammo: u8,
+    pub bullet_limits: BulletLimits,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            starting_ammo: STARTING_AMMO,
+            bullet_limits: BulletLimits::FOREVER,
+        }
+    }
+}
+
+// How much ammo the player can choose to start with, clicking the setting goes to the next one
+const STARTING_AMMO_CHOICES: [u8; 3] = [STARTING_AMMO, 5, 10];
+
+const BULLET_LIMIT_CHOICES: [BulletLimits; 4] = [
+    BulletLimits::FOREVER,
+    BulletLimits {
+        lifetime: Some(Duration::from_secs(10)),
+        max_bounces: None,
+    },
+    BulletLimits {
+        lifetime: None,
+        max_bounces: Some(5),
+    },
+    BulletLimits {
+        lifetime: Some(Duration::from_secs(10)),
+        max_bounces: Some(5),
+    },
+];
+
+// The choice after the current one, wrapping back around to the first
+fn next_choice<T: Copy + PartialEq>(choices: &[T], current: T) -> T {
+    let next_index = choices
+        .iter()
+        .position(|choice| *choice == current)
+        .map_or(0, |index| (index + 1) % choices.len());
+
+    choices[next_index]
+}
+
+#[derive(Component)]
+struct MainMenuScreen;
+
+#[derive(Component)]
+struct SettingsScreen;
+
+#[derive(Component)]
+struct PauseScreen;
+
+// What happens when a menu button gets clicked
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    Start,
+    Settings,
+    Quit,
+    Back,
+    StartingAmmo,
+    BulletLimits,
+}
+
+// The text inside of a button
+#[derive(Component)]
+struct ButtonText(MenuButton);
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+
+// A full screen column with everything in the middle
+fn menu_screen(background_color: Color) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        background_color: background_color.into(),
+        ..Default::default()
+    }
+}
+
+fn text_style(ui_font: &UiFont, font_size: f32) -> TextStyle {
+    TextStyle {
+        font: ui_font.0.clone(),
+        font_size,
+        color: Color::WHITE,
+    }
+}
+
+fn spawn_button(parent: &mut ChildBuilder, ui_font: &UiFont, text: &str, menu_button: MenuButton) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(400.0), Val::Px(80.0)),
+                margin: UiRect::all(Val::Px(10.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: BUTTON_COLOR.into(),
+            ..Default::default()
+        })
+        .insert(menu_button)
+        .with_children(|button| {
+            button
+                .spawn(TextBundle::from_section(text, text_style(ui_font, 40.0)))
+                .insert(ButtonText(menu_button));
+        });
+}
+
+fn show_main_menu(mut commands: Commands, ui_font: Res<UiFont>) {
+    commands
+        .spawn(menu_screen(Color::BLACK))
+        .insert(MainMenuScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Game3",
+                text_style(&ui_font, 100.0),
+            ));
+            spawn_button(parent, &ui_font, "Start", MenuButton::Start);
+            spawn_button(parent, &ui_font, "Settings", MenuButton::Settings);
+            spawn_button(parent, &ui_font, "Quit", MenuButton::Quit);
+        });
+}
+
+fn show_settings(mut commands: Commands, ui_font: Res<UiFont>) {
+    commands
+        .spawn(menu_screen(Color::BLACK))
+        .insert(SettingsScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Settings",
+                text_style(&ui_font, 100.0),
+            ));
+            // The text gets filled in by update_setting_labels
+            spawn_button(parent, &ui_font, "", MenuButton::StartingAmmo);
+            spawn_button(parent, &ui_font, "", MenuButton::BulletLimits);
+            spawn_button(parent, &ui_font, "Back", MenuButton::Back);
+        });
+}
+
+fn show_pause_screen(mut commands: Commands, ui_font: Res<UiFont>) {
+    commands
+        // See through so the frozen arena still shows
+        .spawn(menu_screen(Color::rgba(0.0, 0.0, 0.0, 0.6)))
+        .insert(PauseScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Paused",
+                text_style(&ui_font, 100.0),
+            ));
+            parent.spawn(TextBundle::from_section(
+                "Press Esc to resume",
+                text_style(&ui_font, 40.0),
+            ));
+        });
+}
+
+fn press_menu_buttons(
+    mut button_query: Query<
+        (&Interaction, &MenuButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut game_settings: ResMut<GameSettings>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    for (interaction, menu_button, mut background_color) in &mut button_query {
+        *background_color = match interaction {
+            Interaction::Clicked | Interaction::Hovered => HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => BUTTON_COLOR.into(),
+        };
+
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        match menu_button {
+            MenuButton::Start => next_state.set(GameState::Playing),
+            MenuButton::Settings => next_state.set(GameState::Settings),
+            MenuButton::Quit => app_exit_events.send(AppExit),
+            MenuButton::Back => next_state.set(GameState::MainMenu),
+            MenuButton::StartingAmmo => {
+                game_settings.starting_ammo =
+                    next_choice(&STARTING_AMMO_CHOICES, game_settings.starting_ammo);
+            }
+            MenuButton::BulletLimits => {
+                game_settings.bullet_limits =
+                    next_choice(&BULLET_LIMIT_CHOICES, game_settings.bullet_limits);
+            }
+        }
+    }
+}
+
+// Settings buttons show what they are currently set to
+fn update_setting_labels(
+    mut button_text_query: Query<(&mut Text, &ButtonText)>,
+    added_button_text_query: Query<(), Added<ButtonText>>,
+    game_settings: Res<GameSettings>,
+) {
+    if !game_settings.is_changed() && added_button_text_query.is_empty() {
+        return;
+    }
+
+    for (mut text, ButtonText(menu_button)) in &mut button_text_query {
+        match menu_button {
+            MenuButton::StartingAmmo => {
+                text.sections[0].value = format!("Starting ammo: {}", game_settings.starting_ammo);
+            }
+            MenuButton::BulletLimits => {
+                text.sections[0].value = bullet_limits_label(&game_settings.bullet_limits);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn bullet_limits_label(bullet_limits: &BulletLimits) -> String {
+    match (bullet_limits.lifetime, bullet_limits.max_bounces) {
+        (None, None) => "Bullets last forever".to_string(),
+        (Some(lifetime), None) => format!("Bullets last {}s", lifetime.as_secs()),
+        (None, Some(max_bounces)) => format!("Bullets last {max_bounces} bounces"),
+        (Some(lifetime), Some(max_bounces)) => format!(
+            "Bullets last {}s or {max_bounces} bounces",
+            lifetime.as_secs()
+        ),
+    }
+}
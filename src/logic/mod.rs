@@ -1,7 +1,14 @@
 pub mod ammo;
 pub mod bullet;
+pub mod cli;
 pub mod enemy;
+pub mod health;
+pub mod input;
+pub mod lives;
+pub mod menu;
 pub mod physics;
 pub mod player;
+pub mod rng;
 pub mod score;
+pub mod state;
 pub mod walls;
@@ -1,7 +1,8 @@
-use crate::logic::bullet::{Bullet, BULLET_SIZE};
-use crate::BulletSprite;
+use crate::logic::state::GameState;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy::sprite::collide_aabb::{collide, Collision};
+use bevy::transform::TransformSystem;
+use bevy::utils::{HashMap, HashSet};
 use itertools::Itertools;
 use std::cmp::Ordering;
 
@@ -9,72 +10,300 @@ pub struct PhysicsPlugin;
 
 const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
 
+// How many times a continuous collider can bounce in a single tick.
+const MAX_SWEEP_ITERATIONS: usize = 4;
+// How far a continuous collider is kept away from whatever it hit so that
+// the discrete collision check doesn't pick up the same collision again.
+pub const SWEEP_SKIN: f32 = 0.01;
+
+// Twice the size of a wall block, so most things only ever sit in a few cells.
+const SPATIAL_HASH_CELL_SIZE: f32 = 80.0;
+
 // #[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
 // struct FixedTimeSet;
 
+// Gameplay systems that react to collisions run after this set in the fixed schedule.
+#[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
+pub struct PhysicsSet;
+
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems_to_schedule(
             CoreSchedule::FixedUpdate,
             (
+                sweep_continuous_colliders,
                 move_transforms,
                 detect_collisions,
-                apply_system_buffers,
                 reflect_entity,
-                stop_moving_entity,
+                push_out_of_collisions,
+                destroy_entity,
+                record_physics_transforms,
                 apply_system_buffers,
             )
-                .chain(),
+                .chain()
+                .in_set(PhysicsSet)
+                .distributive_run_if(in_state(GameState::Playing)),
+        )
+        .add_system(restore_physics_transforms.in_base_set(CoreSet::First))
+        // No fixed steps run physics while the game is paused or over, so the last two positions stay frozen.
+        // How far along the next step is still changes every frame, so interpolating would jitter between them.
+        .add_system(
+            interpolate_transforms
+                .in_base_set(CoreSet::PostUpdate)
+                .before(TransformSystem::TransformPropagate)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_system(
+            reseed_moved_transforms
+                .in_base_set(CoreSet::PostUpdate)
+                .before(interpolate_transforms),
         )
         .insert_resource(FixedTime::new_from_secs(FIXED_TIMESTEP))
-        .add_event::<ShootingEvent>()
-        .add_system(shoot);
-    }
-}
-// NOTE
-// The invariant here is that each entity can only have one kind of collision
-// Where you might run into trouble in the future is when you want to have an
-// entity collide with one thing in one way, but collide with another thing in another way.
-// Which is actually going to be a problem haha
-// But ill get there in the future
-// One problem at a time
-// This problem might also be solved by relations lol
-#[derive(Component)]
+        .init_resource::<CollisionRules>()
+        .init_resource::<Broadphase>()
+        .init_resource::<SensorOverlaps>()
+        .add_event::<CollisionEvent>()
+        .add_event::<SensorEvent>()
+        .add_system(toggle_broadphase.run_if(resource_exists::<Input<KeyCode>>()));
+    }
+}
+// The default collision response of an entity.
+// This gets used whenever the collision rules don't have an entry for the pair.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ColliderType {
     // Reflect the moving object
     Reflect,
     // Stop the moving object
     Stop,
-    // Destroy both this object and the colliding object
-    // The entity refers to the entity of the other component
-    // you need to touch in order for desturuction to happen
-    // NOTE this is blocked by relations
-    // Destroy(Entity),
+    // Destroy the moving object
+    Destroy,
     // Does nothing on collision, used for static objects
     Nothing,
+    // Never changes how anything moves, only sends sensor events
+    // when something starts or stops overlapping it
+    Sensor,
+}
+
+impl ColliderType {
+    // Whether the response moves the entity back out of whatever it collided with
+    fn pushes_out(&self) -> bool {
+        matches!(self, ColliderType::Reflect | ColliderType::Stop)
+    }
+}
+
+// What kind of thing the collider is, so that the collision rules can
+// pick a different response depending on what it collided with.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CollisionTag {
+    Wall,
+    Player,
+    Enemy,
+    EnemySpawn,
+    Bullet,
+    Ammo,
+}
+
+// Which layers a collider is on and which layers it interacts with.
+// A pair of colliders is only checked if each one is on a layer the other one interacts with.
+// Colliders without this component are on every layer and interact with everything.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CollisionLayers {
+    pub memberships: u32,
+    pub filters: u32,
+}
+
+impl CollisionLayers {
+    pub const WALL: u32 = 1 << 0;
+    pub const PLAYER: u32 = 1 << 1;
+    pub const ENEMY: u32 = 1 << 2;
+    pub const ENEMY_SPAWN: u32 = 1 << 3;
+    pub const BULLET: u32 = 1 << 4;
+    pub const AMMO: u32 = 1 << 5;
+    pub const ALL: u32 = u32::MAX;
+
+    pub fn new(memberships: u32, filters: u32) -> Self {
+        CollisionLayers {
+            memberships,
+            filters,
+        }
+    }
+
+    fn interacts_with(&self, other: &CollisionLayers) -> bool {
+        self.memberships & other.filters != 0 && other.memberships & self.filters != 0
+    }
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        CollisionLayers::new(CollisionLayers::ALL, CollisionLayers::ALL)
+    }
+}
+
+// The collision response table.
+// An entry for (a, b) is how an entity tagged `a` responds when it collides with an entity tagged `b`,
+// so a bullet can reflect off of walls but get destroyed by another bullet.
+#[derive(Resource)]
+pub struct CollisionRules {
+    responses: HashMap<(CollisionTag, CollisionTag), ColliderType>,
+}
+
+impl CollisionRules {
+    pub fn set(
+        &mut self,
+        tag: CollisionTag,
+        other_tag: CollisionTag,
+        response: ColliderType,
+    ) -> &mut Self {
+        self.responses.insert((tag, other_tag), response);
+        self
+    }
+
+    fn response(
+        &self,
+        tag: Option<&CollisionTag>,
+        other_tag: Option<&CollisionTag>,
+    ) -> Option<ColliderType> {
+        match (tag, other_tag) {
+            (Some(tag), Some(other_tag)) => self.responses.get(&(*tag, *other_tag)).copied(),
+            _ => None,
+        }
+    }
+}
+
+impl Default for CollisionRules {
+    fn default() -> Self {
+        let responses = [
+            (
+                (CollisionTag::Bullet, CollisionTag::Wall),
+                ColliderType::Reflect,
+            ),
+            // Bullets only stop bouncing when they hit another bullet or an enemy.
+            // The bullet plugin consumes bullets that hit each other, enemies and the player
+            // since it also needs to tell everything else what got hit.
+            (
+                (CollisionTag::Bullet, CollisionTag::Bullet),
+                ColliderType::Nothing,
+            ),
+            (
+                (CollisionTag::Bullet, CollisionTag::Enemy),
+                ColliderType::Nothing,
+            ),
+            (
+                (CollisionTag::Bullet, CollisionTag::Player),
+                ColliderType::Nothing,
+            ),
+            (
+                (CollisionTag::Enemy, CollisionTag::Bullet),
+                ColliderType::Nothing,
+            ),
+            (
+                (CollisionTag::Player, CollisionTag::Bullet),
+                ColliderType::Nothing,
+            ),
+            // Enemies have to actually touch the player to kill them.
+            (
+                (CollisionTag::Enemy, CollisionTag::Player),
+                ColliderType::Nothing,
+            ),
+            (
+                (CollisionTag::Player, CollisionTag::Enemy),
+                ColliderType::Nothing,
+            ),
+            (
+                (CollisionTag::Enemy, CollisionTag::Enemy),
+                ColliderType::Stop,
+            ),
+        ];
+
+        let mut rules = CollisionRules {
+            responses: HashMap::default(),
+        };
+
+        for ((tag, other_tag), response) in responses {
+            rules.set(tag, other_tag, response);
+        }
+
+        rules
+    }
+}
+
+// The shape used for collision detection.
+// This is separate from the sprite so that hitboxes don't have to match the art.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    // Where the center of the shape is relative to the transform.
+    // The offset does not rotate with the transform.
+    pub offset: Vec2,
+    // How bouncy the collider is. The restitution of both colliders gets multiplied together,
+    // so below 1.0 a reflecting object loses speed on each bounce, and above 1.0 it speeds up.
+    pub restitution: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ColliderShape {
+    // A box with the given full width and height
+    Aabb(Vec2),
+    // A circle with the given radius
+    Circle(f32),
+}
+
+impl Collider {
+    pub fn aabb(size: Vec2) -> Self {
+        Collider {
+            shape: ColliderShape::Aabb(size),
+            offset: Vec2::ZERO,
+            restitution: 1.0,
+        }
+    }
+
+    pub fn circle(radius: f32) -> Self {
+        Collider {
+            shape: ColliderShape::Circle(radius),
+            offset: Vec2::ZERO,
+            restitution: 1.0,
+        }
+    }
+
+    fn center(&self, transform: &Transform) -> Vec2 {
+        transform.translation.truncate() + self.offset
+    }
+
+    // Half the size of the box that bounds the shape
+    fn half_size(&self) -> Vec2 {
+        match self.shape {
+            ColliderShape::Aabb(size) => size / 2.0,
+            ColliderShape::Circle(radius) => Vec2::splat(radius),
+        }
+    }
 }
 
 struct ColliderInfo<'a> {
     transform: &'a Transform,
-    sprite: &'a Sprite,
+    collider: &'a Collider,
     collider_type: &'a ColliderType,
+    collision_tag: Option<&'a CollisionTag>,
+    layers: CollisionLayers,
     movement: Option<&'a Movement>,
     entity: Entity,
 }
 
-// This is the specific kind of ordering I want for sort and sweep.
-fn interval_ordering(col_info1: &ColliderInfo, col_info2: &ColliderInfo) -> Ordering {
-    let interval1_start =
-        col_info1.transform.translation.x - col_info1.sprite.custom_size.unwrap().x / 2.0;
-
-    let interval2_start =
-        col_info2.transform.translation.x - col_info2.sprite.custom_size.unwrap().x / 2.0;
+impl ColliderInfo<'_> {
+    fn min_x(&self) -> f32 {
+        self.collider.center(self.transform).x - self.collider.half_size().x
+    }
 
-    let interval1_end =
-        col_info1.transform.translation.x + col_info1.sprite.custom_size.unwrap().x / 2.0;
+    fn max_x(&self) -> f32 {
+        self.collider.center(self.transform).x + self.collider.half_size().x
+    }
+}
 
-    let interval2_end =
-        col_info2.transform.translation.x + col_info2.sprite.custom_size.unwrap().x / 2.0;
+// This is the specific kind of ordering I want for sort and sweep.
+fn interval_ordering(col_info1: &ColliderInfo, col_info2: &ColliderInfo) -> Ordering {
+    let interval1_start = col_info1.min_x();
+    let interval2_start = col_info2.min_x();
+    let interval1_end = col_info1.max_x();
+    let interval2_end = col_info2.max_x();
 
     if interval1_start < interval2_start {
         return Ordering::Less;
@@ -97,167 +326,520 @@ fn interval_ordering(col_info1: &ColliderInfo, col_info2: &ColliderInfo) -> Orde
     Ordering::Equal
 }
 
-fn group_by_helper(collider_info_one: &ColliderInfo, collider_info_two: &ColliderInfo) -> bool {
-    let interval_one_end = collider_info_one.transform.translation.x
-        + collider_info_one.sprite.custom_size.unwrap().x / 2.0;
+// Which broadphase detect_collisions uses to find pairs of colliders that might be touching.
+#[derive(Resource, Clone, Copy, Debug)]
+pub enum Broadphase {
+    // Sorts the colliders on the x axis and checks every pair in a group of overlapping intervals.
+    // The walls on the left and right of the arena all share an x interval,
+    // so the groups get big once there are lots of bullets.
+    SortAndSweep,
+    // Buckets the colliders into a uniform grid and only checks pairs that share a cell.
+    SpatialHash { cell_size: f32 },
+}
+
+impl Default for Broadphase {
+    fn default() -> Self {
+        Broadphase::SpatialHash {
+            cell_size: SPATIAL_HASH_CELL_SIZE,
+        }
+    }
+}
 
-    let interval_two_start = collider_info_two.transform.translation.x
-        - collider_info_two.sprite.custom_size.unwrap().x / 2.0;
+// Switch between the broadphases with F3 to compare how they perform
+fn toggle_broadphase(keyboard_input: Res<Input<KeyCode>>, mut broadphase: ResMut<Broadphase>) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        *broadphase = match *broadphase {
+            Broadphase::SortAndSweep => Broadphase::default(),
+            Broadphase::SpatialHash { .. } => Broadphase::SortAndSweep,
+        };
 
-    interval_two_start <= interval_one_end
+        info!("Using the {:?} broadphase", *broadphase);
+    }
 }
 
-fn sort_and_sweep<'a>(collider_infos: &'a mut Vec<ColliderInfo>) -> Vec<&'a [ColliderInfo<'a>]> {
+// Returns the pairs of indices into the collider infos that might be colliding.
+// The collider infos get sorted along the way.
+fn sort_and_sweep(collider_infos: &mut [ColliderInfo]) -> Vec<(usize, usize)> {
     collider_infos.sort_unstable_by(|collider_info_one, collider_info_two| {
         interval_ordering(collider_info_one, collider_info_two)
     });
 
-    collider_infos
-        .group_by(|collider_info_one, collider_info_two| {
-            group_by_helper(collider_info_one, collider_info_two)
+    let mut pairs = vec![];
+    let mut group_start = 0;
+    let mut group_end = f32::NEG_INFINITY;
+
+    // A group ends whenever an interval starts after every interval in the group has ended.
+    // Checking only the interval right before it isn't enough, a long wall can still
+    // overlap things that come after a short bullet.
+    for index in 0..=collider_infos.len() {
+        let group_ended =
+            index == collider_infos.len() || collider_infos[index].min_x() > group_end;
+
+        if group_ended {
+            pairs.extend((group_start..index).tuple_combinations::<(usize, usize)>());
+            group_start = index;
+            group_end = f32::NEG_INFINITY;
+        }
+
+        if let Some(collider_info) = collider_infos.get(index) {
+            group_end = group_end.max(collider_info.max_x());
+        }
+    }
+
+    pairs
+}
+
+// Returns the pairs of indices into the collider infos that share a cell in the grid.
+// Pairs where neither collider is moving can never collide, so they get left out.
+fn spatial_hash(collider_infos: &[ColliderInfo], cell_size: f32) -> Vec<(usize, usize)> {
+    let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::default();
+
+    for (index, collider_info) in collider_infos.iter().enumerate() {
+        let center = collider_info.collider.center(collider_info.transform);
+        let half_size = collider_info.collider.half_size();
+        let min_cell = ((center - half_size) / cell_size).floor();
+        let max_cell = ((center + half_size) / cell_size).floor();
+
+        for x in min_cell.x as i32..=max_cell.x as i32 {
+            for y in min_cell.y as i32..=max_cell.y as i32 {
+                cells.entry((x, y)).or_default().push(index);
+            }
+        }
+    }
+
+    // Colliders that span a few cells would show up more than once
+    let mut pairs: Vec<(usize, usize)> = cells
+        .values()
+        .flat_map(|indices| {
+            indices
+                .iter()
+                .copied()
+                .tuple_combinations::<(usize, usize)>()
+        })
+        .filter(|(one, two)| {
+            collider_infos[*one].movement.is_some() || collider_infos[*two].movement.is_some()
         })
-        .filter(|vec2_slice| vec2_slice.len() != 1)
-        .collect::<Vec<&[ColliderInfo]>>()
+        .collect::<HashSet<(usize, usize)>>()
+        .into_iter()
+        .collect();
+
+    // The hash set doesn't keep an order, so sort the pairs so that
+    // the collision events always come out in the same order.
+    pairs.sort_unstable();
+
+    pairs
 }
 
-fn aabb_collisions<'a>(
-    possibly_colliding_entities: (&'a ColliderInfo, &'a ColliderInfo),
-) -> Option<CollisionInfo<'a>> {
-    let a_movement = possibly_colliding_entities.0.movement;
-    let b_movement = possibly_colliding_entities.1.movement;
+fn aabb_collisions(
+    possibly_colliding_entities: (&ColliderInfo, &ColliderInfo),
+    collision_rules: &CollisionRules,
+) -> Vec<CollisionEvent> {
+    let (a, b) = possibly_colliding_entities;
+
+    if !a.layers.interacts_with(&b.layers) {
+        return vec![];
+    }
 
-    match (a_movement, b_movement) {
-        // Invariant: Only Destroy happens when two moving objects collide (so far)
-        // NOTE I believe that destroy functionality needs relations
-        (Some(_a_movement), Some(_b_movement)) => None,
+    match (a.movement, b.movement) {
+        // Both objects are moving, so both of them get a collision response.
+        (Some(_a_movement), Some(_b_movement)) => {
+            let mut collisions: Vec<CollisionEvent> = [
+                determine_collision(a, b, collision_response(a, b, collision_rules)),
+                determine_collision(b, a, collision_response(b, a, collision_rules)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+
+            // Two objects that both get pushed out of each other each move half the distance,
+            // otherwise they would both get pushed the full overlap and end up apart.
+            if collisions.len() == 2
+                && collisions
+                    .iter()
+                    .all(|collision_event| collision_event.kind.pushes_out())
+            {
+                for collision_event in &mut collisions {
+                    collision_event.depth *= 0.5;
+                }
+            }
+
+            collisions
+        }
         (Some(_movement), None) => {
-            determine_collision(possibly_colliding_entities.0, possibly_colliding_entities.1)
+            determine_collision(a, b, collision_response(a, b, collision_rules))
+                .into_iter()
+                .collect()
         }
         (None, Some(_movement)) => {
-            determine_collision(possibly_colliding_entities.1, possibly_colliding_entities.0)
+            determine_collision(b, a, collision_response(b, a, collision_rules))
+                .into_iter()
+                .collect()
         }
-        (None, None) => None,
+        (None, None) => vec![],
+    }
+}
+
+// Looks up how the moving collider responds to the other collider in the collision rules.
+// Falls back on the collider's own collider type if there is no rule for the pair.
+fn collision_response(
+    moving_collider_info: &ColliderInfo,
+    other_collider_info: &ColliderInfo,
+    collision_rules: &CollisionRules,
+) -> ColliderType {
+    if let Some(response) = collision_rules.response(
+        moving_collider_info.collision_tag,
+        other_collider_info.collision_tag,
+    ) {
+        return response;
     }
+
+    let collider_type = *moving_collider_info.collider_type;
+
+    // When two untagged moving objects collide, objects that stop only get stopped by other
+    // objects that stop. This way a bullet hitting something doesn't shove it out of the way.
+    if other_collider_info.movement.is_some()
+        && collider_type == ColliderType::Stop
+        && *other_collider_info.collider_type != ColliderType::Stop
+    {
+        return ColliderType::Nothing;
+    }
+
+    collider_type
 }
 
 // Determines whether there a collision will occur in the next frame and then
 // delegates to another function that determines what kind of collision response there will be.
-fn determine_collision<'a>(
-    moving_collider_info: &'a ColliderInfo,
-    static_collider_info: &'a ColliderInfo,
-) -> Option<CollisionInfo<'a>> {
+fn determine_collision(
+    moving_collider_info: &ColliderInfo,
+    other_collider_info: &ColliderInfo,
+    collider_type: ColliderType,
+) -> Option<CollisionEvent> {
     let ColliderInfo {
         transform: moving_transform,
-        sprite: moving_sprite,
-        collider_type,
-        movement: _,
+        collider: moving_collider,
         entity: moving_entity,
+        ..
     } = moving_collider_info;
 
     let ColliderInfo {
-        transform: static_transform,
-        sprite: static_sprite,
-        collider_type: _b_collider_type,
-        movement: _b_movement,
-        entity: static_entity,
-    } = static_collider_info;
-
-    let moving_transform_size = moving_sprite.custom_size.unwrap();
-    let static_transform_size = static_sprite.custom_size.unwrap();
-
-    // The return value is the side of `B` that `A` has collided with. `Left` means that
-    // `A` collided with `B`'s left side. `Top` means that `A` collided with `B`'s top side.
-    // If the collision occurs on multiple sides, the side with the deepest penetration is returned.
-    // If all sides are involved, `Inside` is returned.
-
-    collide(
-        moving_transform.translation,
-        moving_transform_size,
-        static_transform.translation,
-        static_transform_size,
+        transform: other_transform,
+        collider: other_collider,
+        entity: other_entity,
+        ..
+    } = other_collider_info;
+
+    contact(
+        moving_collider.center(moving_transform),
+        &moving_collider.shape,
+        other_collider.center(other_transform),
+        &other_collider.shape,
     )
-    .and_then(|collision| {
-        Some(CollisionInfo {
-            collision,
-            collider_type,
-            moving_entity,
-            static_entity,
+    .map(|Contact { normal, depth }| CollisionEvent {
+        a: *moving_entity,
+        b: *other_entity,
+        normal,
+        kind: collider_type,
+        depth,
+    })
+}
+
+// Where two shapes are touching.
+struct Contact {
+    // Points away from the second shape
+    normal: Vec2,
+    // How far the first shape has to move along the normal to stop overlapping
+    depth: f32,
+}
+
+// Checks whether two shapes overlap. Shapes that are only just touching don't count.
+fn contact(
+    center: Vec2,
+    shape: &ColliderShape,
+    other_center: Vec2,
+    other_shape: &ColliderShape,
+) -> Option<Contact> {
+    match (shape, other_shape) {
+        (ColliderShape::Aabb(size), ColliderShape::Aabb(other_size)) => {
+            aabb_aabb_contact(center, *size / 2.0, other_center, *other_size / 2.0)
+        }
+        (ColliderShape::Circle(radius), ColliderShape::Circle(other_radius)) => {
+            circle_circle_contact(center, *radius, other_center, *other_radius)
+        }
+        (ColliderShape::Circle(radius), ColliderShape::Aabb(other_size)) => {
+            circle_aabb_contact(center, *radius, other_center, *other_size / 2.0)
+        }
+        // Flip the circle vs box contact around so the normal points away from the circle
+        (ColliderShape::Aabb(size), ColliderShape::Circle(other_radius)) => {
+            circle_aabb_contact(other_center, *other_radius, center, *size / 2.0).map(
+                |Contact { normal, depth }| Contact {
+                    normal: -normal,
+                    depth,
+                },
+            )
+        }
+    }
+}
+
+// Pushes the box out along whichever axis it overlaps the least on.
+fn aabb_aabb_contact(
+    center: Vec2,
+    half_size: Vec2,
+    other_center: Vec2,
+    other_half_size: Vec2,
+) -> Option<Contact> {
+    let difference = center - other_center;
+    let overlap = half_size + other_half_size - difference.abs();
+
+    if overlap.x <= 0.0 || overlap.y <= 0.0 {
+        return None;
+    }
+
+    if overlap.x < overlap.y {
+        Some(Contact {
+            normal: Vec2::new(sign(difference.x), 0.0),
+            depth: overlap.x,
+        })
+    } else {
+        Some(Contact {
+            normal: Vec2::new(0.0, sign(difference.y)),
+            depth: overlap.y,
         })
+    }
+}
+
+fn circle_circle_contact(
+    center: Vec2,
+    radius: f32,
+    other_center: Vec2,
+    other_radius: f32,
+) -> Option<Contact> {
+    let difference = center - other_center;
+    let distance = difference.length();
+
+    if distance >= radius + other_radius {
+        return None;
+    }
+
+    // Circles right on top of each other can get pushed out in any direction
+    let normal = if distance > 0.0 {
+        difference / distance
+    } else {
+        Vec2::Y
+    };
+
+    Some(Contact {
+        normal,
+        depth: radius + other_radius - distance,
     })
 }
 
+// The normal points from the closest point on the box to the center of the circle,
+// so a circle hitting the corner of a box bounces off at an angle.
+fn circle_aabb_contact(
+    center: Vec2,
+    radius: f32,
+    box_center: Vec2,
+    box_half_size: Vec2,
+) -> Option<Contact> {
+    let box_min = box_center - box_half_size;
+    let box_max = box_center + box_half_size;
+    let closest_point = center.clamp(box_min, box_max);
+    let difference = center - closest_point;
+    let distance = difference.length();
+
+    if distance > 0.0 {
+        if distance >= radius {
+            return None;
+        }
+
+        return Some(Contact {
+            normal: difference / distance,
+            depth: radius - distance,
+        });
+    }
+
+    // The center of the circle is inside the box, so push it out the closest side
+    aabb_aabb_contact(center, Vec2::ZERO, box_center, box_half_size).map(
+        |Contact { normal, depth }| Contact {
+            normal,
+            depth: depth + radius,
+        },
+    )
+}
+
+// Like f32::signum, but zero counts as positive so the normal is never zero
+fn sign(value: f32) -> f32 {
+    if value < 0.0 {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+// Everything about a collider that goes into a ColliderInfo
+type ColliderQuery = (
+    &'static Transform,
+    &'static Collider,
+    &'static ColliderType,
+    Option<&'static CollisionTag>,
+    Option<&'static CollisionLayers>,
+    Option<&'static Movement>,
+    Entity,
+);
+
 // Checks whether objects with collision components have collided.
 fn detect_collisions(
-    collisions_query: Query<(
-        &Transform,
-        &Sprite,
-        &ColliderType,
-        Option<&Movement>,
-        Entity,
-    )>,
-    mut commands: Commands,
+    collisions_query: Query<ColliderQuery>,
+    collision_rules: Res<CollisionRules>,
+    broadphase: Res<Broadphase>,
+    mut sensor_overlaps: ResMut<SensorOverlaps>,
+    mut collision_events: EventWriter<CollisionEvent>,
+    mut sensor_events: EventWriter<SensorEvent>,
 ) {
-    let mut test = collisions_query
+    let mut collider_infos = collisions_query
         .iter()
         .map(
-            |(transform, sprite, collider_type, movement, entity)| ColliderInfo {
-                transform,
-                sprite,
-                collider_type,
-                movement,
-                entity,
+            |(transform, collider, collider_type, collision_tag, layers, movement, entity)| {
+                ColliderInfo {
+                    transform,
+                    collider,
+                    collider_type,
+                    collision_tag,
+                    layers: layers.copied().unwrap_or_default(),
+                    movement,
+                    entity,
+                }
             },
         )
         .collect::<Vec<ColliderInfo>>();
 
-    let test: Vec<&[ColliderInfo]> = sort_and_sweep(&mut test);
+    let possibly_colliding_pairs = match *broadphase {
+        Broadphase::SortAndSweep => sort_and_sweep(&mut collider_infos),
+        Broadphase::SpatialHash { cell_size } => spatial_hash(&collider_infos, cell_size),
+    };
 
-    for possibly_colliding_entities in test {
-        let collisions: Vec<CollisionInfo> = possibly_colliding_entities
-            .iter()
-            .tuple_combinations::<(&ColliderInfo, &ColliderInfo)>()
-            .map(|entity_combinations| aabb_collisions(entity_combinations))
-            .flatten()
-            .collect();
+    let mut overlapping_sensors = HashSet::default();
 
-        // Might not need this. Head hurts but im close lol
-        for CollisionInfo {
-            collision,
-            collider_type,
-            moving_entity,
-            static_entity,
-        } in collisions
-        {
-            println!("collision");
-            match collider_type {
-                ColliderType::Reflect => {
-                    commands
-                        .entity(*moving_entity)
-                        .insert(Reflection { collision });
-                }
-                ColliderType::Stop => {
-                    commands.entity(*moving_entity).insert(Stop {
-                        collision,
-                        static_entity: *static_entity,
-                    });
-                }
-                ColliderType::Nothing => {}
-            };
+    for (one, two) in possibly_colliding_pairs {
+        let possibly_colliding_entities = (&collider_infos[one], &collider_infos[two]);
+
+        if is_sensor_pair(possibly_colliding_entities) {
+            overlapping_sensors.extend(sensor_overlaps_for(possibly_colliding_entities));
+        } else {
+            collision_events.send_batch(aabb_collisions(
+                possibly_colliding_entities,
+                &collision_rules,
+            ));
         }
     }
+
+    update_sensor_overlaps(
+        &mut sensor_overlaps,
+        overlapping_sensors,
+        &mut sensor_events,
+    );
 }
 
-struct CollisionInfo<'a> {
-    collision: Collision,
-    collider_type: &'a ColliderType,
-    moving_entity: &'a Entity,
-    static_entity: &'a Entity,
+fn is_sensor_pair(possibly_colliding_entities: (&ColliderInfo, &ColliderInfo)) -> bool {
+    let (a, b) = possibly_colliding_entities;
+
+    *a.collider_type == ColliderType::Sensor || *b.collider_type == ColliderType::Sensor
 }
 
-// TODO this component should be a sparseset component
-#[derive(Component)]
-struct Reflection {
-    collision: Collision,
+// Returns a (sensor, other) pair for each sensor in the pair if the two colliders overlap.
+fn sensor_overlaps_for(
+    possibly_colliding_entities: (&ColliderInfo, &ColliderInfo),
+) -> Vec<(Entity, Entity)> {
+    let (a, b) = possibly_colliding_entities;
+
+    // Sensors only notice moving things, the same as every other collider
+    if !a.layers.interacts_with(&b.layers) || (a.movement.is_none() && b.movement.is_none()) {
+        return vec![];
+    }
+
+    let overlapping = contact(
+        a.collider.center(a.transform),
+        &a.collider.shape,
+        b.collider.center(b.transform),
+        &b.collider.shape,
+    )
+    .is_some();
+
+    if !overlapping {
+        return vec![];
+    }
+
+    [(a, b), (b, a)]
+        .into_iter()
+        .filter(|(sensor, _)| *sensor.collider_type == ColliderType::Sensor)
+        .map(|(sensor, other)| (sensor.entity, other.entity))
+        .collect()
+}
+
+// The (sensor, other) pairs that were overlapping on the last tick
+#[derive(Resource, Default)]
+struct SensorOverlaps {
+    overlapping: HashSet<(Entity, Entity)>,
+}
+
+// Compares the sensors overlapping this tick to the ones overlapping last tick
+// and sends events for the ones that changed.
+fn update_sensor_overlaps(
+    sensor_overlaps: &mut SensorOverlaps,
+    overlapping_sensors: HashSet<(Entity, Entity)>,
+    sensor_events: &mut EventWriter<SensorEvent>,
+) {
+    // Sorted so the events come out in the same order every time
+    let mut started: Vec<(Entity, Entity)> = overlapping_sensors
+        .difference(&sensor_overlaps.overlapping)
+        .copied()
+        .collect();
+    started.sort_unstable();
+
+    let mut stopped: Vec<(Entity, Entity)> = sensor_overlaps
+        .overlapping
+        .difference(&overlapping_sensors)
+        .copied()
+        .collect();
+    stopped.sort_unstable();
+
+    sensor_events.send_batch(
+        stopped
+            .into_iter()
+            .map(|(sensor, other)| SensorEvent::Stopped { sensor, other }),
+    );
+    sensor_events.send_batch(
+        started
+            .into_iter()
+            .map(|(sensor, other)| SensorEvent::Started { sensor, other }),
+    );
+
+    sensor_overlaps.overlapping = overlapping_sensors;
+}
+
+// Sent when something starts or stops overlapping a sensor.
+// If either entity gets despawned while they overlap, a Stopped event gets sent on the next tick.
+#[derive(Debug)]
+pub enum SensorEvent {
+    Started { sensor: Entity, other: Entity },
+    Stopped { sensor: Entity, other: Entity },
+}
+
+// Sent every time two colliders touch, once for each moving collider in the pair.
+// Static colliders never get one, and the event still gets sent when the response is Nothing,
+// since gameplay systems like bullets hitting enemies rely on it.
+// Gameplay systems that care about something getting hit should read these after the PhysicsSet.
+pub struct CollisionEvent {
+    // The entity the collision response applies to
+    pub a: Entity,
+    // The entity `a` collided with
+    pub b: Entity,
+    // Points away from `b`, the direction `a` gets pushed out in
+    pub normal: Vec2,
+    // The response `a` has to the collision
+    pub kind: ColliderType,
+    // How far `a` gets pushed along the normal to resolve the collision
+    pub depth: f32,
 }
 
 // TODO
@@ -286,131 +868,90 @@ struct Reflection {
 // I think a key takeaway from all this is that when and how I run my systems is now important for me to make progress in my game. It is no longer
 // practical for me to just say "everything runs in parallel". Practically, I need to get an idea for the ordering of systems in my game.
 
-// Reflects the velocity of the entity
-fn reflect_entity(mut query: Query<(&Reflection, &mut Movement, Entity)>, mut commands: Commands) {
-    for (reflection, mut movement, entity) in &mut query {
-        println!("{:?}", reflection.collision);
-        match reflection.collision {
-            Collision::Left => {
-                movement.velocity = Vec3::new(movement.velocity.x * -1.0, movement.velocity.y, 0.0)
-            }
-            Collision::Right => {
-                movement.velocity = Vec3::new(movement.velocity.x * -1.0, movement.velocity.y, 0.0)
-            }
-            Collision::Top => {
-                movement.velocity = Vec3::new(movement.velocity.x, movement.velocity.y * -1.0, 0.0)
-            }
-            Collision::Bottom => {
-                movement.velocity = Vec3::new(movement.velocity.x, movement.velocity.y * -1.0, 0.0)
-            }
-            Collision::Inside => {}
+// Reflects the velocity of the entity across the contact normal.
+// Only velocity moving into the surface gets reflected, so touching two walls
+// on the same side in a tick doesn't flip the velocity back again.
+fn reflect_entity(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut query: Query<&mut Movement>,
+    collider_query: Query<&Collider>,
+) {
+    for collision_event in collision_events.iter() {
+        if collision_event.kind != ColliderType::Reflect {
+            continue;
         }
 
-        commands.entity(entity).remove::<Reflection>();
+        let restitution = [collision_event.a, collision_event.b]
+            .into_iter()
+            .filter_map(|entity| collider_query.get(entity).ok())
+            .map(|collider| collider.restitution)
+            .product();
+
+        if let Ok(mut movement) = query.get_mut(collision_event.a) {
+            movement.velocity =
+                reflect_velocity(movement.velocity, collision_event.normal, restitution);
+        }
     }
 }
 
-// TODO this component should be a sparseset component
-#[derive(Component)]
-struct Stop {
-    collision: Collision,
-    static_entity: Entity,
+// Bounces the velocity off of a surface with the given normal.
+// The restitution scales the speed coming out of the surface.
+fn reflect_velocity(velocity: Vec3, normal: Vec2, restitution: f32) -> Vec3 {
+    let normal = normal.extend(0.0);
+    let speed_into_surface = velocity.dot(normal);
+
+    if speed_into_surface >= 0.0 {
+        return velocity;
+    }
+
+    velocity - (1.0 + restitution) * speed_into_surface * normal
 }
 
-// Resets the position of the moving transform to be the position right before the collision.
-fn stop_moving_entity(
-    mut moving_transform_query: Query<(&Stop, &mut Transform, &Sprite, Entity)>,
-    static_transform_query: Query<(&Transform, &Sprite, Without<Stop>)>,
-    mut commands: Commands,
+// Moves entities that stopped or reflected back out of whatever they collided with,
+// so they don't end up stuck inside of it on the next tick.
+// If the entity is touching a few colliders on the same side, like a row of wall blocks,
+// it only gets pushed out by the deepest one.
+fn push_out_of_collisions(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut moving_transform_query: Query<&mut Transform, With<Movement>>,
 ) {
-    for (stop, mut moving_transform, moving_sprite, entity) in &mut moving_transform_query {
-        let Stop {
-            collision,
-            static_entity,
-        } = stop;
-
-        let moving_transform_size = moving_sprite.custom_size.unwrap();
-        let static_transform = static_transform_query
-            .get_component::<Transform>(*static_entity)
-            .unwrap();
-
-        let static_transform_size = static_transform_query
-            .get_component::<Sprite>(*static_entity)
-            .unwrap()
-            .custom_size
-            .unwrap();
-
-        match collision {
-            Collision::Left => {
-                let one_x_pos = static_transform.translation.x - static_transform_size.x / 2.0;
-                let two_cur_x_pos = moving_transform.translation.x + moving_transform_size.x / 2.0;
-                moving_transform.translation.x =
-                    (one_x_pos - two_cur_x_pos) + moving_transform.translation.x;
-            }
-            Collision::Right => {
-                let one_x_pos = static_transform.translation.x + static_transform_size.x / 2.0;
-                let two_cur_x_pos = moving_transform.translation.x - moving_transform_size.x / 2.0;
+    let mut corrections: HashMap<Entity, Vec2> = HashMap::default();
 
-                moving_transform.translation.x =
-                    (one_x_pos - two_cur_x_pos) + moving_transform.translation.x;
-            }
-            Collision::Top => {
-                let one_y_pos = static_transform.translation.y + static_transform_size.y / 2.0;
-                let two_cur_y_pos = moving_transform.translation.y - moving_transform_size.y / 2.0;
+    for collision_event in collision_events.iter() {
+        if !collision_event.kind.pushes_out() {
+            continue;
+        }
 
-                moving_transform.translation.y =
-                    (one_y_pos - two_cur_y_pos) + moving_transform.translation.y;
-            }
-            Collision::Bottom => {
-                let one_y_pos = static_transform.translation.y - static_transform_size.y / 2.0;
-                let two_cur_y_pos = moving_transform.translation.y + moving_transform_size.y / 2.0;
+        let push = collision_event.normal * collision_event.depth;
+        let correction = corrections.entry(collision_event.a).or_insert(Vec2::ZERO);
 
-                moving_transform.translation.y =
-                    (one_y_pos - two_cur_y_pos) + moving_transform.translation.y;
-            }
-            Collision::Inside => {}
+        if push.x.abs() > correction.x.abs() {
+            correction.x = push.x;
         }
 
-        commands.entity(entity).remove::<Stop>();
+        if push.y.abs() > correction.y.abs() {
+            correction.y = push.y;
+        }
+    }
+
+    for (entity, correction) in corrections {
+        if let Ok(mut moving_transform) = moving_transform_query.get_mut(entity) {
+            moving_transform.translation += correction.extend(0.0);
+        }
     }
 }
 
-// Specifies that the entity can shoot
-#[derive(Component)]
-pub struct ShootingEvent(pub Entity);
+// Despawns the entity. An entity can get destroyed by more than one collision in a tick,
+// so this makes sure each entity only gets despawned once.
+fn destroy_entity(mut collision_events: EventReader<CollisionEvent>, mut commands: Commands) {
+    let destroyed_entities: HashSet<Entity> = collision_events
+        .iter()
+        .filter(|collision_event| collision_event.kind == ColliderType::Destroy)
+        .map(|collision_event| collision_event.a)
+        .collect();
 
-// Spawn a bullet that moves in the direction the transform is facing.
-fn shoot(
-    mut shooting_event: EventReader<ShootingEvent>,
-    mut commands: Commands,
-    transform_query: Query<&Transform>,
-    bullet_sprite: Res<BulletSprite>,
-) {
-    for ShootingEvent(entity) in shooting_event.iter() {
-        let transform = transform_query
-            .get_component::<Transform>(*entity)
-            .expect("Everything that shoots needs a transform.");
-
-        commands
-            .spawn(SpriteBundle {
-                sprite: Sprite {
-                    custom_size: Some(Vec2::new(BULLET_SIZE, BULLET_SIZE)),
-                    ..Default::default()
-                },
-                texture: bullet_sprite.0.clone(),
-                // Scale the local y unit vector so that the bullet does not
-                // immediately collide with the transform.
-                transform: Transform::from_translation(
-                    transform.translation + transform.local_y() * 50.0,
-                ),
-                ..Default::default()
-            })
-            .insert(Bullet)
-            .insert(Movement {
-                // 400.0 is the speed of the bullets
-                velocity: transform.local_y() * 400.0,
-            })
-            .insert(ColliderType::Reflect);
+    for entity in destroyed_entities {
+        commands.entity(entity).despawn();
     }
 }
 
@@ -421,8 +962,895 @@ pub struct Movement {
 }
 
 // Does fairly basic linear movement
-fn move_transforms(mut query: Query<(&mut Transform, &Movement)>) {
+fn move_transforms(mut query: Query<(&mut Transform, &Movement), Without<ContinuousCollision>>) {
     for (mut transform, movement) in &mut query {
         transform.translation += movement.velocity * FIXED_TIMESTEP as f32;
     }
 }
+
+// Fast moving objects can move further than the width of a wall in a single tick
+// and tunnel straight through it, since the collision check only looks at where the object ends up.
+// Objects with this component get swept along their path instead and reflect off of
+// static colliders at the point they actually hit them.
+#[derive(Component)]
+pub struct ContinuousCollision;
+
+// Where a moving entity was, and which way it was facing, at the end of the last two fixed steps.
+// The physics only ever sees `current`, the rendered transform is somewhere in between the two
+// so that motion looks smooth when frames don't line up with fixed steps.
+#[derive(Component)]
+struct InterpolationState {
+    previous: Vec3,
+    current: Vec3,
+    // Aiming happens in the fixed step too, so turning needs smoothing as much as moving does
+    previous_rotation: Quat,
+    current_rotation: Quat,
+}
+
+impl InterpolationState {
+    // Nothing to interpolate from, the entity is just where the transform says it is
+    fn at(transform: &Transform) -> Self {
+        InterpolationState {
+            previous: transform.translation,
+            current: transform.translation,
+            previous_rotation: transform.rotation,
+            current_rotation: transform.rotation,
+        }
+    }
+}
+
+// Puts moving entities back where the physics left them, undoing last frame's interpolation.
+// This runs first thing in the frame so every other system sees the real positions.
+fn restore_physics_transforms(mut query: Query<(&mut Transform, &InterpolationState)>) {
+    for (mut transform, interpolation_state) in &mut query {
+        transform.translation = interpolation_state.current;
+        transform.rotation = interpolation_state.current_rotation;
+    }
+}
+
+fn record_physics_transforms(
+    mut query: Query<(&Transform, Option<&mut InterpolationState>, Entity), With<Movement>>,
+    mut commands: Commands,
+) {
+    for (transform, interpolation_state, entity) in &mut query {
+        match interpolation_state {
+            Some(mut interpolation_state) => {
+                interpolation_state.previous = interpolation_state.current;
+                interpolation_state.current = transform.translation;
+                interpolation_state.previous_rotation = interpolation_state.current_rotation;
+                interpolation_state.current_rotation = transform.rotation;
+            }
+            // Just started moving, so there is nothing to interpolate from yet
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(InterpolationState::at(transform));
+            }
+        }
+    }
+}
+
+// Anything that moves an entity outside of the fixed step, like a teleport in Update or a respawn
+// in OnEnter, would get undone by restore_physics_transforms next frame.
+// The physics left the entity at `current`, so if it's somewhere else now it was moved on purpose
+// and that's where it should be from now on, with nothing to interpolate from.
+fn reseed_moved_transforms(mut query: Query<(&Transform, &mut InterpolationState)>) {
+    for (transform, mut interpolation_state) in &mut query {
+        if transform.translation != interpolation_state.current
+            || transform.rotation != interpolation_state.current_rotation
+        {
+            *interpolation_state = InterpolationState::at(transform);
+        }
+    }
+}
+
+// Moves the rendered transform between the last two fixed steps by how far along we are to the next one.
+fn interpolate_transforms(
+    mut query: Query<(&mut Transform, &InterpolationState)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let alpha = (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).min(1.0);
+
+    for (mut transform, interpolation_state) in &mut query {
+        transform.translation = interpolation_state
+            .previous
+            .lerp(interpolation_state.current, alpha);
+        transform.rotation = interpolation_state
+            .previous_rotation
+            .slerp(interpolation_state.current_rotation, alpha);
+    }
+}
+
+// A collider that gets swept along its path, it moves so it needs its transform and movement mutably
+type ContinuousColliderQuery = (
+    &'static mut Transform,
+    &'static mut Movement,
+    &'static Collider,
+    &'static ColliderType,
+    Option<&'static CollisionTag>,
+    Option<&'static CollisionLayers>,
+    Entity,
+);
+
+// A collider that doesn't move, which continuous colliders get swept against
+type StaticColliderQuery = (
+    &'static Transform,
+    &'static Collider,
+    &'static ColliderType,
+    Option<&'static CollisionTag>,
+    Option<&'static CollisionLayers>,
+    Entity,
+);
+
+// Moves continuous colliders, reflecting them off of static colliders they would hit along the way.
+// Collisions with other moving objects are still handled by detect_collisions.
+fn sweep_continuous_colliders(
+    mut continuous_query: Query<ContinuousColliderQuery, With<ContinuousCollision>>,
+    static_query: Query<StaticColliderQuery, Without<Movement>>,
+    collision_rules: Res<CollisionRules>,
+    mut collision_events: EventWriter<CollisionEvent>,
+) {
+    for (mut transform, mut movement, collider, collider_type, collision_tag, layers, entity) in
+        &mut continuous_query
+    {
+        let layers = layers.copied().unwrap_or_default();
+
+        // Only sweep against the colliders this object would reflect off of.
+        let reflecting_colliders: Vec<(Vec2, Vec2, f32, Entity)> = static_query
+            .iter()
+            .filter(
+                |(_, _, static_collider_type, static_tag, static_layers, _)| {
+                    **static_collider_type != ColliderType::Sensor
+                        && layers.interacts_with(&static_layers.copied().unwrap_or_default())
+                        && collision_rules
+                            .response(collision_tag, *static_tag)
+                            .unwrap_or(*collider_type)
+                            == ColliderType::Reflect
+                },
+            )
+            .map(
+                |(static_transform, static_collider, _, _, _, static_entity)| {
+                    (
+                        static_collider.center(static_transform),
+                        static_collider.half_size(),
+                        static_collider.restitution,
+                        static_entity,
+                    )
+                },
+            )
+            .collect();
+
+        let mut remaining_time = FIXED_TIMESTEP;
+
+        for _ in 0..MAX_SWEEP_ITERATIONS {
+            let position = collider.center(&transform);
+            let displacement = movement.velocity.truncate() * remaining_time;
+
+            let earliest_hit = reflecting_colliders
+                .iter()
+                .filter_map(
+                    |(static_position, static_half_size, static_restitution, static_entity)| {
+                        // Circles get swept against the box with its corners rounded off by the radius,
+                        // so a bullet clipping a corner bounces away from the corner instead of off of a side.
+                        match collider.shape {
+                            ColliderShape::Aabb(size) => swept_aabb(
+                                position,
+                                size / 2.0,
+                                displacement,
+                                *static_position,
+                                *static_half_size,
+                            ),
+                            ColliderShape::Circle(radius) => swept_circle(
+                                position,
+                                radius,
+                                displacement,
+                                *static_position,
+                                *static_half_size,
+                            ),
+                        }
+                        .map(|(time_of_impact, normal)| {
+                            (time_of_impact, normal, *static_restitution, *static_entity)
+                        })
+                    },
+                )
+                .min_by(|(time_one, ..), (time_two, ..)| time_one.total_cmp(time_two));
+
+            match earliest_hit {
+                None => {
+                    transform.translation += displacement.extend(0.0);
+                    break;
+                }
+                Some((time_of_impact, normal, static_restitution, static_entity)) => {
+                    let contact_point =
+                        position + displacement * time_of_impact + normal * SWEEP_SKIN;
+                    transform.translation =
+                        (contact_point - collider.offset).extend(transform.translation.z);
+
+                    movement.velocity = reflect_velocity(
+                        movement.velocity,
+                        normal,
+                        collider.restitution * static_restitution,
+                    );
+
+                    remaining_time *= 1.0 - time_of_impact;
+
+                    // The velocity is already reflected, so reflect_entity leaves this one alone.
+                    collision_events.send(CollisionEvent {
+                        a: entity,
+                        b: static_entity,
+                        normal,
+                        kind: ColliderType::Reflect,
+                        depth: 0.0,
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Finds when a box moving by `displacement` first touches a static box.
+// Returns the fraction of the displacement travelled before the hit and the normal of the side that got hit.
+// This is a ray cast against the static box grown by the size of the moving box.
+fn swept_aabb(
+    position: Vec2,
+    half_size: Vec2,
+    displacement: Vec2,
+    static_position: Vec2,
+    static_half_size: Vec2,
+) -> Option<(f32, Vec2)> {
+    let expanded_min = static_position - static_half_size - half_size;
+    let expanded_max = static_position + static_half_size + half_size;
+
+    let mut entry_time = f32::NEG_INFINITY;
+    let mut exit_time = f32::INFINITY;
+    let mut normal = Vec2::ZERO;
+
+    for axis in 0..2 {
+        if displacement[axis] == 0.0 {
+            // Not moving on this axis, so it has to already be between the sides.
+            if position[axis] <= expanded_min[axis] || position[axis] >= expanded_max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let time_one = (expanded_min[axis] - position[axis]) / displacement[axis];
+        let time_two = (expanded_max[axis] - position[axis]) / displacement[axis];
+        let (axis_entry, axis_exit) = (time_one.min(time_two), time_one.max(time_two));
+
+        if axis_entry > entry_time {
+            entry_time = axis_entry;
+            normal = Vec2::ZERO;
+            normal[axis] = -displacement[axis].signum();
+        }
+        exit_time = exit_time.min(axis_exit);
+    }
+
+    // Already overlapping at the start of the sweep is left to the discrete collision check.
+    if !(0.0..=1.0).contains(&entry_time) || entry_time >= exit_time {
+        return None;
+    }
+
+    Some((entry_time, normal))
+}
+
+// Finds when a circle moving by `displacement` first touches a static box, the same way as swept_aabb.
+// The box grown by the radius is two boxes, one wider and one taller, plus a circle on each corner.
+// The first time the center touches any of those is when the circle touches the box.
+fn swept_circle(
+    position: Vec2,
+    radius: f32,
+    displacement: Vec2,
+    static_position: Vec2,
+    static_half_size: Vec2,
+) -> Option<(f32, Vec2)> {
+    let closest_point = position.clamp(
+        static_position - static_half_size,
+        static_position + static_half_size,
+    );
+
+    // Already overlapping at the start of the sweep is left to the discrete collision check.
+    if position.distance_squared(closest_point) <= radius * radius {
+        return None;
+    }
+
+    let length = displacement.length();
+    if length == 0.0 {
+        return None;
+    }
+
+    let sides = [Vec2::new(radius, 0.0), Vec2::new(0.0, radius)]
+        .into_iter()
+        .filter_map(|grown_by| {
+            swept_aabb(
+                position,
+                grown_by,
+                displacement,
+                static_position,
+                static_half_size,
+            )
+        });
+
+    let corners = [
+        Vec2::new(-1.0, -1.0),
+        Vec2::new(1.0, -1.0),
+        Vec2::new(-1.0, 1.0),
+        Vec2::new(1.0, 1.0),
+    ]
+    .into_iter()
+    .filter_map(|corner| {
+        ray_circle(
+            position,
+            displacement / length,
+            length,
+            static_position + static_half_size * corner,
+            radius,
+        )
+        .map(|(distance, normal)| (distance / length, normal))
+    });
+
+    sides
+        .chain(corners)
+        .min_by(|(time_one, _), (time_two, _)| time_one.total_cmp(time_two))
+}
+
+// Lets systems ask questions about the physics world, like "can I see the player?"
+// Casts only hit colliders where they are at the start of the system, they don't look at anything that moves afterwards.
+#[derive(SystemParam)]
+pub struct PhysicsQuery<'w, 's> {
+    colliders: Query<
+        'w,
+        's,
+        (
+            &'static Transform,
+            &'static Collider,
+            &'static ColliderType,
+            Option<&'static CollisionLayers>,
+            Entity,
+        ),
+    >,
+}
+
+impl<'w, 's> PhysicsQuery<'w, 's> {
+    // Finds the first collider a ray hits within `max_distance`.
+    pub fn cast_ray(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &CastFilter,
+    ) -> Option<CastHit> {
+        self.cast(origin, Vec2::ZERO, direction, max_distance, filter)
+    }
+
+    // Finds the first collider a box with the given size hits when moved `max_distance` along `direction`.
+    // The point of the hit is where the center of the box is when it touches the collider.
+    // Circles get cast against as the box around them, the same as sweep_continuous_colliders.
+    pub fn cast_aabb(
+        &self,
+        origin: Vec2,
+        size: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &CastFilter,
+    ) -> Option<CastHit> {
+        self.cast(origin, size / 2.0, direction, max_distance, filter)
+    }
+
+    fn cast(
+        &self,
+        origin: Vec2,
+        half_size: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &CastFilter,
+    ) -> Option<CastHit> {
+        let direction = direction.try_normalize()?;
+
+        self.colliders
+            .iter()
+            .filter(|(_, _, collider_type, layers, entity)| {
+                filter.allows(
+                    **collider_type,
+                    layers.copied().unwrap_or_default(),
+                    *entity,
+                )
+            })
+            .filter_map(|(transform, collider, _, _, entity)| {
+                let center = collider.center(transform);
+
+                let distance_and_normal = match collider.shape {
+                    ColliderShape::Circle(radius) if half_size == Vec2::ZERO => {
+                        ray_circle(origin, direction, max_distance, center, radius)
+                    }
+                    _ => swept_aabb(
+                        origin,
+                        half_size,
+                        direction * max_distance,
+                        center,
+                        collider.half_size(),
+                    )
+                    .map(|(time_of_impact, normal)| (time_of_impact * max_distance, normal)),
+                };
+
+                distance_and_normal.map(|(distance, normal)| CastHit {
+                    entity,
+                    point: origin + direction * distance,
+                    normal,
+                    distance,
+                })
+            })
+            .min_by(|hit_one, hit_two| hit_one.distance.total_cmp(&hit_two.distance))
+    }
+}
+
+// Decides which colliders a cast can hit. Sensors never get hit.
+#[derive(Clone, Debug)]
+pub struct CastFilter {
+    // Only colliders that are a member of one of these layers get hit
+    pub layers: u32,
+    // Entities the cast goes straight through, like whatever is doing the casting
+    pub excluded: Vec<Entity>,
+}
+
+impl CastFilter {
+    pub fn new(layers: u32) -> Self {
+        Self {
+            layers,
+            excluded: vec![],
+        }
+    }
+
+    pub fn excluding(mut self, entity: Entity) -> Self {
+        self.excluded.push(entity);
+        self
+    }
+
+    fn allows(&self, collider_type: ColliderType, layers: CollisionLayers, entity: Entity) -> bool {
+        collider_type != ColliderType::Sensor
+            && layers.memberships & self.layers != 0
+            && !self.excluded.contains(&entity)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CastHit {
+    pub entity: Entity,
+    pub point: Vec2,
+    // Points away from the collider that got hit
+    pub normal: Vec2,
+    pub distance: f32,
+}
+
+// Finds how far along a ray it first hits a circle, and the normal there.
+// Rays that start inside the circle don't hit it, the same as swept_aabb.
+fn ray_circle(
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    center: Vec2,
+    radius: f32,
+) -> Option<(f32, Vec2)> {
+    let from_center = origin - center;
+    let projection = from_center.dot(direction);
+    let outside = from_center.length_squared() - radius * radius;
+
+    if outside <= 0.0 {
+        return None;
+    }
+
+    let discriminant = projection * projection - outside;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let distance = -projection - discriminant.sqrt();
+    if !(0.0..=max_distance).contains(&distance) {
+        return None;
+    }
+
+    let normal = (origin + direction * distance - center) / radius;
+
+    Some((distance, normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A collider in a test scene, with a velocity if it's moving
+    struct SceneCollider {
+        transform: Transform,
+        collider: Collider,
+        movement: Option<Movement>,
+    }
+
+    fn scene_collider(position: Vec2, collider: Collider, moving: bool) -> SceneCollider {
+        SceneCollider {
+            transform: Transform::from_translation(position.extend(0.0)),
+            collider,
+            movement: moving.then_some(Movement {
+                velocity: Vec3::new(100.0, 50.0, 0.0),
+            }),
+        }
+    }
+
+    fn collider_infos(scene: &[SceneCollider]) -> Vec<ColliderInfo<'_>> {
+        scene
+            .iter()
+            .enumerate()
+            .map(|(index, scene_collider)| ColliderInfo {
+                transform: &scene_collider.transform,
+                collider: &scene_collider.collider,
+                collider_type: &ColliderType::Reflect,
+                collision_tag: None,
+                layers: CollisionLayers::default(),
+                movement: scene_collider.movement.as_ref(),
+                entity: Entity::from_raw(index as u32),
+            })
+            .collect()
+    }
+
+    // The pairs out of the candidates that actually touch, the same way detect_collisions would find them
+    fn touching_pairs(
+        collider_infos: &[ColliderInfo],
+        candidates: impl IntoIterator<Item = (usize, usize)>,
+    ) -> HashSet<(Entity, Entity)> {
+        candidates
+            .into_iter()
+            .map(|(one, two)| (&collider_infos[one], &collider_infos[two]))
+            .filter(|(one, two)| one.movement.is_some() || two.movement.is_some())
+            .filter(|(one, two)| {
+                contact(
+                    one.collider.center(one.transform),
+                    &one.collider.shape,
+                    two.collider.center(two.transform),
+                    &two.collider.shape,
+                )
+                .is_some()
+            })
+            .map(|(one, two)| (one.entity.min(two.entity), one.entity.max(two.entity)))
+            .collect()
+    }
+
+    // Checks that both broadphases find every pair that checking every pair against every other finds
+    fn assert_broadphases_match_brute_force(scene: &[SceneCollider]) {
+        let mut collider_infos = collider_infos(scene);
+
+        let brute_force = touching_pairs(
+            &collider_infos,
+            (0..collider_infos.len()).tuple_combinations::<(usize, usize)>(),
+        );
+        assert!(
+            !brute_force.is_empty(),
+            "The scene should have some collisions"
+        );
+
+        let spatial_hash_pairs = spatial_hash(&collider_infos, SPATIAL_HASH_CELL_SIZE);
+        assert_eq!(
+            touching_pairs(&collider_infos, spatial_hash_pairs),
+            brute_force
+        );
+
+        let sort_and_sweep_pairs = sort_and_sweep(&mut collider_infos);
+        assert_eq!(
+            touching_pairs(&collider_infos, sort_and_sweep_pairs),
+            brute_force
+        );
+    }
+
+    fn wall_grid_scene() -> Vec<SceneCollider> {
+        let block = Vec2::splat(40.0);
+        let mut scene = vec![];
+
+        // A ring of wall blocks like the arena, with bullets bouncing around inside of it
+        for x in -6i32..=6 {
+            for y in -5i32..=5 {
+                if x.abs() == 6 || y.abs() == 5 {
+                    let position = Vec2::new(x as f32, y as f32) * 40.0;
+                    scene.push(scene_collider(position, Collider::aabb(block), false));
+                }
+            }
+        }
+
+        for position in [
+            Vec2::new(-225.0, 0.0),
+            Vec2::new(0.0, 185.0),
+            Vec2::new(228.0, -190.0),
+            Vec2::new(35.0, 35.0),
+            Vec2::new(-80.0, -185.0),
+        ] {
+            scene.push(scene_collider(position, Collider::circle(10.0), true));
+        }
+
+        scene
+    }
+
+    fn overlapping_bullets_scene() -> Vec<SceneCollider> {
+        let mut scene = vec![];
+
+        // A tight spiral of bullets so lots of them overlap, spread across a few cells
+        for index in 0..60 {
+            let angle = index as f32 * 0.7;
+            let position = Vec2::new(angle.cos(), angle.sin()) * (index as f32 * 4.0);
+            scene.push(scene_collider(position, Collider::circle(10.0), true));
+        }
+
+        scene.push(scene_collider(
+            Vec2::new(-100.0, 60.0),
+            Collider::aabb(Vec2::new(300.0, 40.0)),
+            false,
+        ));
+
+        scene
+    }
+
+    fn touching_edges_scene() -> Vec<SceneCollider> {
+        let block = Vec2::splat(40.0);
+        let mut scene = vec![];
+
+        // Boxes exactly touching don't collide, boxes a hair over do.
+        // They line up with the edges of the spatial hash cells, on both sides of zero.
+        for (index, x) in [-120.0, -80.0, -40.0, 0.0, 40.0, 80.0, 120.0]
+            .into_iter()
+            .enumerate()
+        {
+            scene.push(scene_collider(
+                Vec2::new(x, 0.0),
+                Collider::aabb(block),
+                index % 2 == 0,
+            ));
+            scene.push(scene_collider(
+                Vec2::new(x - 0.01, 40.0),
+                Collider::aabb(block),
+                true,
+            ));
+            scene.push(scene_collider(
+                Vec2::new(x, -79.99),
+                Collider::aabb(block),
+                index % 2 == 1,
+            ));
+        }
+
+        scene
+    }
+
+    #[test]
+    fn broadphases_match_brute_force_on_a_wall_grid() {
+        assert_broadphases_match_brute_force(&wall_grid_scene());
+    }
+
+    #[test]
+    fn broadphases_match_brute_force_on_overlapping_bullets() {
+        assert_broadphases_match_brute_force(&overlapping_bullets_scene());
+    }
+
+    #[test]
+    fn broadphases_match_brute_force_on_touching_edges() {
+        assert_broadphases_match_brute_force(&touching_edges_scene());
+    }
+
+    // How sort and sweep used to group intervals with slice::group_by, only comparing each
+    // interval to the one right before it. A long interval followed by a short one could end
+    // the group too early, so sort_and_sweep now compares against the furthest end in the group.
+    fn adjacent_sort_and_sweep(collider_infos: &mut [ColliderInfo]) -> Vec<(usize, usize)> {
+        collider_infos.sort_unstable_by(interval_ordering);
+
+        let mut pairs = vec![];
+        let mut group_start = 0;
+
+        for index in 1..=collider_infos.len() {
+            let group_ended = index == collider_infos.len()
+                || collider_infos[index].min_x() > collider_infos[index - 1].max_x();
+
+            if group_ended {
+                pairs.extend((group_start..index).tuple_combinations::<(usize, usize)>());
+                group_start = index;
+            }
+        }
+
+        pairs
+    }
+
+    // The candidate pairs as entities, since each sort puts the collider infos in its own order
+    fn candidate_entities(
+        collider_infos: &[ColliderInfo],
+        pairs: Vec<(usize, usize)>,
+    ) -> HashSet<(Entity, Entity)> {
+        pairs
+            .into_iter()
+            .map(|(one, two)| (collider_infos[one].entity, collider_infos[two].entity))
+            .map(|(one, two)| (one.min(two), one.max(two)))
+            .collect()
+    }
+
+    // Grouping differently is on purpose, but it can only ever add candidates, never lose any
+    #[test]
+    fn sort_and_sweep_keeps_every_pair_the_adjacent_grouping_found() {
+        for scene in [
+            wall_grid_scene(),
+            overlapping_bullets_scene(),
+            touching_edges_scene(),
+        ] {
+            let mut collider_infos = collider_infos(&scene);
+            let adjacent_pairs = adjacent_sort_and_sweep(&mut collider_infos);
+            let adjacent_pairs = candidate_entities(&collider_infos, adjacent_pairs);
+
+            let pairs = sort_and_sweep(&mut collider_infos);
+            let pairs = candidate_entities(&collider_infos, pairs);
+
+            assert!(pairs.is_superset(&adjacent_pairs));
+        }
+
+        // The long wall in this scene is where the old grouping missed pairs
+        let scene = overlapping_bullets_scene();
+        let mut collider_infos = collider_infos(&scene);
+        let adjacent_pairs = adjacent_sort_and_sweep(&mut collider_infos);
+        let adjacent_pairs = touching_pairs(&collider_infos, adjacent_pairs);
+        let pairs = sort_and_sweep(&mut collider_infos);
+
+        assert!(touching_pairs(&collider_infos, pairs).len() > adjacent_pairs.len());
+    }
+
+    fn assert_close(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-4),
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn circle_hitting_a_box_corner_gets_pushed_away_from_the_corner() {
+        let contact = circle_aabb_contact(
+            Vec2::new(12.0, 12.0),
+            5.0,
+            Vec2::ZERO,
+            Vec2::new(10.0, 10.0),
+        )
+        .expect("The circle overlaps the corner");
+
+        assert_close(contact.normal, Vec2::new(1.0, 1.0).normalize());
+        assert!((contact.depth - (5.0 - 2.0 * 2.0_f32.sqrt())).abs() < 1e-4);
+    }
+
+    #[test]
+    fn circle_with_its_center_inside_a_box_gets_pushed_out_the_closest_side() {
+        let contact = circle_aabb_contact(Vec2::new(8.0, 0.0), 5.0, Vec2::ZERO, Vec2::splat(10.0))
+            .expect("The circle is inside the box");
+
+        assert_close(contact.normal, Vec2::X);
+        assert!((contact.depth - 7.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn contact_flips_the_normal_for_a_box_hitting_a_circle() {
+        let contact = contact(
+            Vec2::ZERO,
+            &ColliderShape::Aabb(Vec2::splat(20.0)),
+            Vec2::new(12.0, 12.0),
+            &ColliderShape::Circle(5.0),
+        )
+        .expect("The box overlaps the circle");
+
+        assert_close(contact.normal, -Vec2::new(1.0, 1.0).normalize());
+    }
+
+    #[test]
+    fn just_touching_circles_do_not_collide() {
+        assert!(circle_circle_contact(Vec2::ZERO, 5.0, Vec2::new(10.0, 0.0), 5.0).is_none());
+    }
+
+    #[test]
+    fn reflect_velocity_scales_the_bounce_by_the_restitution() {
+        let velocity = reflect_velocity(Vec3::new(3.0, -10.0, 0.0), Vec2::Y, 0.5);
+
+        assert_close(velocity.truncate(), Vec2::new(3.0, 5.0));
+    }
+
+    #[test]
+    fn reflect_velocity_leaves_velocity_moving_away_from_the_surface_alone() {
+        let velocity = reflect_velocity(Vec3::new(3.0, 10.0, 0.0), Vec2::Y, 0.5);
+
+        assert_close(velocity.truncate(), Vec2::new(3.0, 10.0));
+    }
+
+    #[test]
+    fn swept_aabb_finds_a_head_on_hit() {
+        let (time_of_impact, normal) = swept_aabb(
+            Vec2::new(-30.0, 0.0),
+            Vec2::splat(5.0),
+            Vec2::new(40.0, 0.0),
+            Vec2::ZERO,
+            Vec2::splat(10.0),
+        )
+        .expect("The box moves straight into the other box");
+
+        assert!((time_of_impact - 0.375).abs() < 1e-4);
+        assert_close(normal, Vec2::NEG_X);
+    }
+
+    #[test]
+    fn swept_aabb_finds_a_grazing_hit_and_misses_a_touching_pass() {
+        let grazing = swept_aabb(
+            Vec2::new(-30.0, 14.9),
+            Vec2::splat(5.0),
+            Vec2::new(40.0, 0.0),
+            Vec2::ZERO,
+            Vec2::splat(10.0),
+        )
+        .expect("The edges of the boxes overlap a little");
+
+        assert!((grazing.0 - 0.375).abs() < 1e-4);
+        assert_close(grazing.1, Vec2::NEG_X);
+
+        let sliding_past = swept_aabb(
+            Vec2::new(-30.0, 15.0),
+            Vec2::splat(5.0),
+            Vec2::new(40.0, 0.0),
+            Vec2::ZERO,
+            Vec2::splat(10.0),
+        );
+
+        assert!(sliding_past.is_none());
+    }
+
+    #[test]
+    fn swept_circle_bounces_off_a_corner_at_an_angle() {
+        let (time_of_impact, normal) = swept_circle(
+            Vec2::new(-20.0, -20.0),
+            5.0,
+            Vec2::new(20.0, 20.0),
+            Vec2::ZERO,
+            Vec2::splat(10.0),
+        )
+        .expect("The circle moves straight into the corner");
+
+        let distance_to_corner = Vec2::splat(10.0).length() - 5.0;
+        assert!((time_of_impact - distance_to_corner / Vec2::splat(20.0).length()).abs() < 1e-4);
+        assert_close(normal, -Vec2::new(1.0, 1.0).normalize());
+    }
+
+    #[test]
+    fn swept_circle_misses_a_corner_the_box_around_it_would_hit() {
+        // Passes the corner diagonally, a bit more than the radius away from it
+        let closest_approach = Vec2::splat(10.0) + Vec2::new(1.0, 1.0).normalize() * 6.0;
+        let start = closest_approach + Vec2::new(-20.0, 20.0);
+        let displacement = Vec2::new(40.0, -40.0);
+
+        assert!(swept_aabb(
+            start,
+            Vec2::splat(5.0),
+            displacement,
+            Vec2::ZERO,
+            Vec2::splat(10.0)
+        )
+        .is_some());
+        assert!(swept_circle(start, 5.0, displacement, Vec2::ZERO, Vec2::splat(10.0)).is_none());
+    }
+
+    #[test]
+    fn ray_circle_hits_the_near_side_of_the_circle() {
+        let (distance, normal) = ray_circle(Vec2::ZERO, Vec2::X, 100.0, Vec2::new(20.0, 0.0), 5.0)
+            .expect("The ray points at the circle");
+
+        assert!((distance - 15.0).abs() < 1e-4);
+        assert_close(normal, Vec2::NEG_X);
+    }
+
+    #[test]
+    fn ray_circle_misses_a_circle_off_to_the_side() {
+        assert!(ray_circle(
+            Vec2::new(0.0, 10.0),
+            Vec2::X,
+            100.0,
+            Vec2::new(20.0, 0.0),
+            5.0
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn ray_circle_misses_a_circle_past_the_max_distance() {
+        assert!(ray_circle(Vec2::ZERO, Vec2::X, 10.0, Vec2::new(20.0, 0.0), 5.0).is_none());
+    }
+}
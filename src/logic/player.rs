@@ -1,22 +1,57 @@
-use crate::logic::ammo::{Ammo, BULLET_HEIGHT, BULLET_WIDTH};
-use crate::logic::physics::ShootingEvent;
-use crate::logic::physics::{ColliderType, Movement};
-use crate::{WINDOWHEIGHT, WINDOWWIDTH};
+use crate::logic::ammo::Ammo;
+use crate::logic::bullet::{ProjectileKind, ShootingEvent, ShootingSet};
+use crate::logic::health::{Health, Hurtbox};
+use crate::logic::input::{InputSet, PlayerInput};
+use crate::logic::lives::{PLAYER_INVULNERABILITY, STARTING_LIVES};
+use crate::logic::menu::GameSettings;
+use crate::logic::physics::{Collider, ColliderType, CollisionLayers, CollisionTag, Movement};
+use crate::logic::physics::{PhysicsSet, SensorEvent};
+use crate::logic::state::{despawn_all, GameState, NewGame};
+use crate::PlayerSprite;
 use bevy::prelude::*;
-use bevy::sprite::collide_aabb::collide;
 
 pub struct PlayerPlugin;
 
 pub const PLAYER_SIZE: f32 = 40.0;
+// The player's hitbox is a bit smaller than the sprite so grazes are forgiving
+pub const PLAYER_HITBOX_SIZE: f32 = 30.0;
 pub const STARTING_AMMO: u8 = 3;
+// The weapons the player cycles through, each one shoots a different kind of bullet
+pub const WEAPONS: [ProjectileKind; 4] = [
+    ProjectileKind::NORMAL,
+    ProjectileKind::PIERCING,
+    ProjectileKind::EXPLOSIVE,
+    ProjectileKind::SPLITTING,
+];
+
+// Enemies run after this set in the fixed schedule so they react to what the player did this tick.
+#[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
+pub struct PlayerSet;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_player)
-            .add_system(player_keyboard_input)
-            .add_system(shoot)
-            .add_system(collect_ammo)
-            .add_system(look_at_cursor);
+        app.add_systems_to_schedule(NewGame, (spawn_player, despawn_all::<Player>))
+            // Player input is sampled once per tick, so all of this runs in the fixed schedule
+            .add_systems_to_schedule(
+                CoreSchedule::FixedUpdate,
+                (
+                    player_keyboard_input,
+                    look_at_cursor,
+                    switch_weapon,
+                    shoot.in_set(ShootingSet),
+                )
+                    .chain()
+                    .in_set(PlayerSet)
+                    .after(InputSet)
+                    .before(PhysicsSet)
+                    .distributive_run_if(in_state(GameState::Playing)),
+            )
+            .add_system_to_schedule(
+                CoreSchedule::FixedUpdate,
+                collect_ammo
+                    .after(PhysicsSet)
+                    .run_if(in_state(GameState::Playing)),
+            );
     }
 }
 
@@ -24,11 +59,16 @@ impl Plugin for PlayerPlugin {
 pub struct Player {
     // Ammo is limited to some amount (I forget how much)
     pub ammo: u8,
+    // Which of the WEAPONS the player is holding
+    pub weapon: usize,
 }
 
-fn spawn_player(mut commands: Commands, server: Res<AssetServer>) {
+fn spawn_player(
+    mut commands: Commands,
+    player_sprite: Res<PlayerSprite>,
+    game_settings: Res<GameSettings>,
+) {
     let player_size = Vec2::new(PLAYER_SIZE, PLAYER_SIZE);
-    let handle: Handle<Image> = server.load("sprites/player.png");
 
     commands
         .spawn(SpriteBundle {
@@ -36,61 +76,53 @@ fn spawn_player(mut commands: Commands, server: Res<AssetServer>) {
                 custom_size: Some(player_size),
                 ..Default::default()
             },
-            texture: handle,
+            texture: player_sprite.0.clone(),
             ..Default::default()
         })
         .insert(Player {
-            ammo: STARTING_AMMO,
+            ammo: game_settings.starting_ammo,
+            weapon: 0,
         })
+        .insert(Health::new(STARTING_LIVES).with_invulnerability(PLAYER_INVULNERABILITY))
+        .insert(Hurtbox::Player)
         .insert(Movement {
             velocity: Vec3::new(0.0, 0.0, 0.0),
         })
-        .insert(ColliderType::Stop);
+        .insert(Collider::aabb(Vec2::new(
+            PLAYER_HITBOX_SIZE,
+            PLAYER_HITBOX_SIZE,
+        )))
+        .insert(ColliderType::Stop)
+        .insert(CollisionTag::Player)
+        .insert(CollisionLayers::new(
+            CollisionLayers::PLAYER,
+            CollisionLayers::ALL,
+        ));
 }
 
 // Move the player with WASD or the arrow keys
 fn player_keyboard_input(
     mut player_query: Query<&mut Movement, With<Player>>,
-    keyboard_input: Res<Input<KeyCode>>,
+    player_input: Res<PlayerInput>,
 ) {
-    let mut movement = player_query
-        .get_single_mut()
-        .expect("A single player was not found");
-
-    let up = keyboard_input.pressed(KeyCode::Up) || keyboard_input.pressed(KeyCode::W);
-    let down = keyboard_input.pressed(KeyCode::Down) || keyboard_input.pressed(KeyCode::S);
-    let left = keyboard_input.pressed(KeyCode::Left) || keyboard_input.pressed(KeyCode::A);
-    let right = keyboard_input.pressed(KeyCode::Right) || keyboard_input.pressed(KeyCode::D);
-
-    // If left is pressed than it will be -1, right 1, both they cancel out.
-    // This indicates the direction of the player using a unit vector
-    let x_axis: i8 = -(left as i8) + right as i8;
-    let y_axis: i8 = -(down as i8) + up as i8;
-    let move_delta: Vec2 = Vec2::new(x_axis as f32, y_axis as f32);
-
-    movement.velocity = Vec3::new(move_delta.x * 400.0, move_delta.y * 400.0, 0.0);
+    // Dying switches to GameOver rather than despawning the player, so this just stops running then
+    let Ok(mut movement) = player_query.get_single_mut() else {
+        return;
+    };
+
+    movement.velocity = (player_input.movement * 400.0).extend(0.0);
 }
 
 // The player always faces the cursor
-fn look_at_cursor(windows: Query<&Window>, mut player_query: Query<&mut Transform, With<Player>>) {
-    // Games typically only have one window (the primary window).
-    // For multi-window applications, you need to use a specific window ID here.
-    let window = windows.single();
-
-    // cursor is inside the window, position given
-    if let Some(position) = window.cursor_position() {
-        let mut player_transform = player_query
-            .get_single_mut()
-            .expect("Could not find a single player");
-
-        // The position of the cursor is given from (0,0) in the top left to (screen width, screen height) in the bottom right.
-        // Most bevy coordinates are done with (0,0) in the middle of the screen.
-        // This translates from screen space to world space.
-        let world_space_cursor_vec = position + Vec2::new(-WINDOWWIDTH / 2.0, -WINDOWHEIGHT / 2.0);
-
-        // Get the vector from the player to the cursor in 2D and normalize it.
-        let to_cursor =
-            (world_space_cursor_vec - player_transform.translation.truncate()).normalize();
+fn look_at_cursor(
+    mut player_query: Query<&mut Transform, With<Player>>,
+    player_input: Res<PlayerInput>,
+) {
+    // cursor is inside the window, direction given
+    if let Some(to_cursor) = player_input.aim {
+        let Ok(mut player_transform) = player_query.get_single_mut() else {
+            return;
+        };
 
         // Get the quaternion to rotate the player to the cursor.
         // The player is facing up.
@@ -101,21 +133,34 @@ fn look_at_cursor(windows: Query<&Window>, mut player_query: Query<&mut Transfor
     }
 }
 
+// The player switches to the next weapon with Q or right click
+fn switch_weapon(mut player_query: Query<&mut Player>, player_input: Res<PlayerInput>) {
+    let Ok(mut player) = player_query.get_single_mut() else {
+        return;
+    };
+
+    if player_input.switch_weapon {
+        player.weapon = (player.weapon + 1) % WEAPONS.len();
+    }
+}
+
 // The player shoots with space
 fn shoot(
     mut player_query: Query<(Entity, &mut Player)>,
-    keyboard_input: Res<Input<KeyCode>>,
-    buttons: Res<Input<MouseButton>>,
+    player_input: Res<PlayerInput>,
     mut event_writer: EventWriter<ShootingEvent>,
 ) {
-    let (player_entity, mut player) = player_query
-        .get_single_mut()
-        .expect("Could not find a single player");
+    let Ok((player_entity, mut player)) = player_query.get_single_mut() else {
+        return;
+    };
 
-    if keyboard_input.just_pressed(KeyCode::Space) || buttons.just_pressed(MouseButton::Left) {
+    if player_input.fire {
         // The player cannot shoot if they have no ammunition
         if player.ammo > 0 {
-            event_writer.send(ShootingEvent(player_entity));
+            event_writer.send(ShootingEvent {
+                shooter: player_entity,
+                kind: WEAPONS[player.weapon],
+            });
             player.ammo -= 1;
         }
     }
@@ -123,21 +168,19 @@ fn shoot(
 
 // Player collects the ammo by passing over it
 fn collect_ammo(
-    mut player_query: Query<(&Transform, &mut Player)>,
-    ammo_query: Query<(&Transform, Entity), (With<Ammo>, Without<Player>)>,
+    mut player_query: Query<&mut Player>,
+    ammo_query: Query<Entity, With<Ammo>>,
+    mut sensor_events: EventReader<SensorEvent>,
     mut commands: Commands,
 ) {
-    let (player_transform, mut player) = player_query
-        .get_single_mut()
-        .expect("Could not find a single player");
-
-    for (ammo_transform, ammo_entity) in &ammo_query {
-        if let Some(_collision) = collide(
-            player_transform.translation,
-            Vec2::new(PLAYER_SIZE, PLAYER_SIZE),
-            ammo_transform.translation,
-            Vec2::new(BULLET_WIDTH, BULLET_HEIGHT),
-        ) {
+    for event in sensor_events.iter() {
+        let SensorEvent::Started { sensor, other } = event else {
+            continue;
+        };
+
+        if let (Ok(ammo_entity), Ok(mut player)) =
+            (ammo_query.get(*sensor), player_query.get_mut(*other))
+        {
             commands.entity(ammo_entity).despawn();
 
             player.ammo += 1;
@@ -0,0 +1,63 @@
+use crate::logic::cli::arg_value;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        // Pass `--seed <number>` to play the exact same game as a previous run
+        let seed = arg_value("--seed")
+            .map(|seed| seed.parse().expect("The seed has to be a positive number"))
+            .unwrap_or_else(rand::random);
+
+        app.insert_resource(GameRng::new(seed))
+            .add_startup_system(log_seed);
+    }
+}
+
+// A replay or the headless runner can swap out the seed after the plugin is added,
+// so this waits until startup to log the one that actually gets used
+fn log_seed(rng: Res<GameRng>) {
+    info!("Game seed: {}", rng.seed());
+}
+
+// All gameplay randomness has to come from here, otherwise runs with the same seed won't match.
+// Only use it from systems in the fixed schedule, the number of frames isn't the same between runs.
+#[derive(Resource)]
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -1,4 +1,6 @@
 use crate::logic::enemy::EnemyDeathEvent;
+use crate::logic::state::NewGame;
+use crate::UiFont;
 use bevy::prelude::*;
 
 pub struct ScorePlugin;
@@ -6,21 +8,22 @@ pub struct ScorePlugin;
 impl Plugin for ScorePlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(spawn_score_ui)
+            .add_system_to_schedule(NewGame, reset_score)
             .add_system(update_score_text);
     }
 }
 
 #[derive(Component)]
-struct Score {
-    value: u32,
+pub struct Score {
+    pub value: u32,
 }
 
-fn spawn_score_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn spawn_score_ui(mut commands: Commands, ui_font: Res<UiFont>) {
     let score_text: Text = Text::from_sections([
         TextSection::new(
             "Score: ",
             TextStyle {
-                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font: ui_font.0.clone(),
                 font_size: 60.0,
                 color: Color::WHITE,
             },
@@ -28,7 +31,7 @@ fn spawn_score_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
         TextSection::new(
             0.to_string(),
             TextStyle {
-                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font: ui_font.0.clone(),
                 font_size: 60.0,
                 color: Color::GOLD,
             },
@@ -69,3 +72,11 @@ fn update_score_text(
         score_text.sections[1].value = score_component.value.to_string();
     }
 }
+
+// Every run starts back at zero
+fn reset_score(mut score_query: Query<(&mut Text, &mut Score)>) {
+    for (mut score_text, mut score) in &mut score_query {
+        score.value = 0;
+        score_text.sections[1].value = score.value.to_string();
+    }
+}
@@ -0,0 +1,133 @@
+use crate::logic::score::Score;
+use crate::UiFont;
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::prelude::*;
+
+pub struct StatePlugin;
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .init_schedule(NewGame)
+            .init_resource::<GameInProgress>()
+            .add_system_to_schedule(
+                OnEnter(GameState::Playing),
+                start_new_game.run_if(no_game_in_progress),
+            )
+            .add_system_to_schedule(OnEnter(GameState::MainMenu), end_game)
+            .add_system_to_schedule(OnEnter(GameState::GameOver), end_game)
+            .add_system_to_schedule(OnEnter(GameState::GameOver), show_game_over_screen)
+            .add_system_to_schedule(OnExit(GameState::GameOver), despawn_all::<GameOverScreen>)
+            .add_system(toggle_pause.run_if(resource_exists::<Input<KeyCode>>()))
+            .add_system(
+                restart
+                    .run_if(in_state(GameState::GameOver))
+                    .run_if(resource_exists::<Input<KeyCode>>()),
+            );
+    }
+}
+
+// Gameplay only runs while Playing, so everything freezes in place while paused or once the player dies.
+#[derive(States, Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    Settings,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+// Runs whenever a new game starts, from the main menu or by restarting after a game over.
+// Every plugin cleans up what was left of the last game and spawns its part of the arena in here.
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NewGame;
+
+// Whether there is a game to go back to. Coming back to Playing from Paused keeps going with it.
+#[derive(Resource, Default)]
+struct GameInProgress(bool);
+
+fn no_game_in_progress(game_in_progress: Res<GameInProgress>) -> bool {
+    !game_in_progress.0
+}
+
+fn start_new_game(world: &mut World) {
+    world.run_schedule(NewGame);
+    world.resource_mut::<GameInProgress>().0 = true;
+}
+
+fn end_game(mut game_in_progress: ResMut<GameInProgress>) {
+    game_in_progress.0 = false;
+}
+
+// Esc pauses and unpauses the game
+fn toggle_pause(
+    keyboard_input: Res<Input<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+
+    match state.0 {
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        _ => {}
+    }
+}
+
+// Despawns every entity with the component, along with their children
+pub fn despawn_all<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[derive(Component)]
+struct GameOverScreen;
+
+fn show_game_over_screen(mut commands: Commands, score_query: Query<&Score>, ui_font: Res<UiFont>) {
+    let score = score_query.get_single().map_or(0, |score| score.value);
+
+    let text_style = |font_size: f32, color: Color| TextStyle {
+        font: ui_font.0.clone(),
+        font_size,
+        color,
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            // Darken the frozen arena behind the text
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(GameOverScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Game Over",
+                text_style(100.0, Color::CRIMSON),
+            ));
+            parent.spawn(TextBundle::from_sections([
+                TextSection::new("Final score: ", text_style(60.0, Color::WHITE)),
+                TextSection::new(score.to_string(), text_style(60.0, Color::GOLD)),
+            ]));
+            parent.spawn(TextBundle::from_section(
+                "Press R to restart",
+                text_style(40.0, Color::WHITE),
+            ));
+        });
+}
+
+fn restart(keyboard_input: Res<Input<KeyCode>>, mut next_state: ResMut<NextState<GameState>>) {
+    if keyboard_input.just_pressed(KeyCode::R) {
+        next_state.set(GameState::Playing);
+    }
+}
@@ -1,16 +1,14 @@
-use crate::logic::physics::ColliderType;
-use crate::{WINDOWHEIGHT, WINDOWWIDTH};
+use crate::logic::physics::{Collider, ColliderType, CollisionLayers, CollisionTag};
+use crate::{WallSprites, WINDOWHEIGHT, WINDOWWIDTH};
 use bevy::prelude::*;
 
 pub struct WallsPlugin;
 
-// TODO My game now lags depending on how many bullets are flying around
-
 // NOTE
 // The size of the blocks matters because I need the corner sprites to match the edge of the screen.
 // This means that the blocks size needs to be a multiple of the width and height of the screen.
 // NOTE
-// Making this any smaller makes the game lag. I suspect its the collision system because I have no broad phase collision detection
+// Making this any smaller used to make the game lag, back when the broadphase was just sort and sweep.
 const BLOCKSIZE: i16 = 40;
 
 impl Plugin for WallsPlugin {
@@ -25,27 +23,30 @@ pub struct Wall {
     pub height: f32,
 }
 
-fn spawn_walls_v2(mut commands: Commands, server: Res<AssetServer>) {
-    let horizontal_wall_handle: Handle<Image> = server.load("sprites/wall-horizontal.png");
-    let vertical_wall_handle: Handle<Image> = server.load("sprites/wall-vertical.png");
-    let top_left_wall_handle: Handle<Image> = server.load("sprites/wall-corner-top-left.png");
-    let top_right_wall_handle: Handle<Image> = server.load("sprites/wall-corner-top-right.png");
-    let bottom_left_wall_handle: Handle<Image> = server.load("sprites/wall-corner-bottom-left.png");
-    let bottom_right_wall_handle: Handle<Image> =
-        server.load("sprites/wall-corner-bottom-right.png");
-
+fn spawn_walls_v2(mut commands: Commands, wall_sprites: Res<WallSprites>) {
     for spawn_location in create_horizontal_spawn_locations() {
         let sprite_bundle = SpriteBundle {
             sprite: Sprite {
                 custom_size: Some(Vec2::new(BLOCKSIZE as f32, BLOCKSIZE as f32)),
                 ..Default::default()
             },
-            texture: horizontal_wall_handle.clone(),
+            texture: wall_sprites.horizontal.clone(),
             transform: Transform::from_translation(spawn_location.extend(0.0)),
             ..Default::default()
         };
 
-        commands.spawn(sprite_bundle).insert(ColliderType::Nothing);
+        commands
+            .spawn(sprite_bundle)
+            .insert(Collider::aabb(Vec2::new(
+                BLOCKSIZE as f32,
+                BLOCKSIZE as f32,
+            )))
+            .insert(ColliderType::Nothing)
+            .insert(CollisionTag::Wall)
+            .insert(CollisionLayers::new(
+                CollisionLayers::WALL,
+                CollisionLayers::ALL,
+            ));
     }
 
     for spawn_location in create_vertical_spawn_locations() {
@@ -54,12 +55,23 @@ fn spawn_walls_v2(mut commands: Commands, server: Res<AssetServer>) {
                 custom_size: Some(Vec2::new(BLOCKSIZE as f32, BLOCKSIZE as f32)),
                 ..Default::default()
             },
-            texture: vertical_wall_handle.clone(),
+            texture: wall_sprites.vertical.clone(),
             transform: Transform::from_translation(spawn_location.extend(0.0)),
             ..Default::default()
         };
 
-        commands.spawn(sprite_bundle).insert(ColliderType::Nothing);
+        commands
+            .spawn(sprite_bundle)
+            .insert(Collider::aabb(Vec2::new(
+                BLOCKSIZE as f32,
+                BLOCKSIZE as f32,
+            )))
+            .insert(ColliderType::Nothing)
+            .insert(CollisionTag::Wall)
+            .insert(CollisionLayers::new(
+                CollisionLayers::WALL,
+                CollisionLayers::ALL,
+            ));
     }
 
     let top_left_wall = SpriteBundle {
@@ -67,7 +79,7 @@ fn spawn_walls_v2(mut commands: Commands, server: Res<AssetServer>) {
             custom_size: Some(Vec2::new(BLOCKSIZE as f32, BLOCKSIZE as f32)),
             ..Default::default()
         },
-        texture: top_left_wall_handle.clone(),
+        texture: wall_sprites.top_left.clone(),
         transform: Transform::from_translation(Vec3::new(
             -WINDOWWIDTH / 2.0 + (BLOCKSIZE / 2) as f32,
             WINDOWHEIGHT / 2.0 - (BLOCKSIZE / 2) as f32,
@@ -81,7 +93,7 @@ fn spawn_walls_v2(mut commands: Commands, server: Res<AssetServer>) {
             custom_size: Some(Vec2::new(BLOCKSIZE as f32, BLOCKSIZE as f32)),
             ..Default::default()
         },
-        texture: top_right_wall_handle.clone(),
+        texture: wall_sprites.top_right.clone(),
         transform: Transform::from_translation(Vec3::new(
             WINDOWWIDTH / 2.0 - (BLOCKSIZE / 2) as f32,
             WINDOWHEIGHT / 2.0 - (BLOCKSIZE / 2) as f32,
@@ -95,7 +107,7 @@ fn spawn_walls_v2(mut commands: Commands, server: Res<AssetServer>) {
             custom_size: Some(Vec2::new(BLOCKSIZE as f32, BLOCKSIZE as f32)),
             ..Default::default()
         },
-        texture: bottom_left_wall_handle.clone(),
+        texture: wall_sprites.bottom_left.clone(),
         transform: Transform::from_translation(Vec3::new(
             -WINDOWWIDTH / 2.0 + (BLOCKSIZE / 2) as f32,
             -WINDOWHEIGHT / 2.0 + (BLOCKSIZE / 2) as f32,
@@ -109,7 +121,7 @@ fn spawn_walls_v2(mut commands: Commands, server: Res<AssetServer>) {
             custom_size: Some(Vec2::new(BLOCKSIZE as f32, BLOCKSIZE as f32)),
             ..Default::default()
         },
-        texture: bottom_right_wall_handle.clone(),
+        texture: wall_sprites.bottom_right.clone(),
         transform: Transform::from_translation(Vec3::new(
             WINDOWWIDTH / 2.0 - (BLOCKSIZE / 2) as f32,
             -WINDOWHEIGHT / 2.0 + (BLOCKSIZE / 2) as f32,
@@ -118,14 +130,25 @@ fn spawn_walls_v2(mut commands: Commands, server: Res<AssetServer>) {
         ..Default::default()
     };
 
-    commands.spawn(top_left_wall).insert(ColliderType::Nothing);
-    commands.spawn(top_right_wall).insert(ColliderType::Nothing);
-    commands
-        .spawn(bottom_left_wall)
-        .insert(ColliderType::Nothing);
-    commands
-        .spawn(bottom_right_wall)
-        .insert(ColliderType::Nothing);
+    for corner_wall in [
+        top_left_wall,
+        top_right_wall,
+        bottom_left_wall,
+        bottom_right_wall,
+    ] {
+        commands
+            .spawn(corner_wall)
+            .insert(Collider::aabb(Vec2::new(
+                BLOCKSIZE as f32,
+                BLOCKSIZE as f32,
+            )))
+            .insert(ColliderType::Nothing)
+            .insert(CollisionTag::Wall)
+            .insert(CollisionLayers::new(
+                CollisionLayers::WALL,
+                CollisionLayers::ALL,
+            ));
+    }
 }
 
 // Getting the positions of the blocks is tricky because I want to remove the corners.
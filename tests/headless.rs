@@ -0,0 +1,141 @@
+use bevy::prelude::*;
+use game3::headless::HeadlessGame;
+use game3::logic::bullet::Bullet;
+use game3::logic::enemy::Enemy;
+use game3::logic::health::{Health, Hurtbox};
+use game3::logic::input::PlayerInput;
+use game3::logic::physics::{Collider, ColliderType, CollisionLayers, CollisionTag, Movement};
+use game3::logic::score::Score;
+
+const SEED: u64 = 42;
+
+// How many fixed ticks it takes for `seconds` of game time to go by
+fn ticks(game: &HeadlessGame, seconds: f32) -> usize {
+    let tick_length = game.app.world.resource::<FixedTime>().period;
+
+    (seconds / tick_length.as_secs_f32()).round() as usize
+}
+
+fn run(game: &mut HeadlessGame, ticks: usize) {
+    for _ in 0..ticks {
+        game.tick(PlayerInput::default());
+    }
+}
+
+fn fire(aim: Vec2) -> PlayerInput {
+    PlayerInput {
+        fire: true,
+        aim: Some(aim),
+        ..Default::default()
+    }
+}
+
+// An enemy that just sits there, so a test knows exactly where it is
+fn spawn_target(game: &mut HeadlessGame, position: Vec2, health: u32) -> Entity {
+    game.app
+        .world
+        .spawn((
+            TransformBundle::from_transform(Transform::from_translation(position.extend(0.0))),
+            Enemy,
+            Health::new(health),
+            Hurtbox::Enemy,
+            Collider::aabb(Vec2::splat(40.0)),
+            ColliderType::Stop,
+            CollisionTag::Enemy,
+            CollisionLayers::new(CollisionLayers::ENEMY, CollisionLayers::ALL),
+        ))
+        .id()
+}
+
+fn health(game: &HeadlessGame, entity: Entity) -> u32 {
+    game.app
+        .world
+        .get::<Health>(entity)
+        .expect("The target should still be alive")
+        .current
+}
+
+#[test]
+fn enemy_spawns_after_the_spawn_timers() {
+    let mut game = HeadlessGame::new(SEED);
+
+    // The first spawn marker shows up after 4 seconds and turns into an enemy 3 seconds later
+    let before_spawn = ticks(&game, 6.5);
+    run(&mut game, before_spawn);
+    assert_eq!(game.count::<Enemy>(), 0);
+
+    let after_spawn = ticks(&game, 1.0);
+    run(&mut game, after_spawn);
+    assert_eq!(game.count::<Enemy>(), 1);
+}
+
+#[test]
+fn bullet_bounces_off_a_wall() {
+    let mut game = HeadlessGame::new(SEED);
+
+    game.tick(fire(Vec2::X));
+
+    let mut bullet_query = game.app.world.query_filtered::<&Movement, With<Bullet>>();
+    let velocity = bullet_query.single(&game.app.world).velocity;
+    assert!(velocity.x > 0.0);
+
+    // The right wall is a bit under 600 units away and bullets go 400 units a second
+    let reach_wall = ticks(&game, 2.0);
+    run(&mut game, reach_wall);
+
+    let bounced = bullet_query.single(&game.app.world).velocity;
+    assert!(bounced.x < 0.0, "The bullet should be heading back left");
+    assert_eq!(bounced.length(), velocity.length());
+}
+
+#[test]
+fn killing_an_enemy_scores_a_point() {
+    let mut game = HeadlessGame::new(SEED);
+    spawn_target(&mut game, Vec2::new(0.0, 200.0), 1);
+
+    let mut score_query = game.app.world.query::<&Score>();
+    assert_eq!(score_query.single(&game.app.world).value, 0);
+
+    game.tick(fire(Vec2::Y));
+    let reach_enemy = ticks(&game, 1.0);
+    run(&mut game, reach_enemy);
+
+    assert_eq!(game.count::<Enemy>(), 0);
+    assert_eq!(score_query.single(&game.app.world).value, 1);
+}
+
+#[test]
+fn quick_shots_both_do_damage() {
+    let mut game = HeadlessGame::new(SEED);
+    let target = spawn_target(&mut game, Vec2::new(0.0, 200.0), 3);
+
+    // The second bullet lands 150ms after the first
+    game.tick(fire(Vec2::Y));
+    let between_shots = ticks(&game, 0.15);
+    run(&mut game, between_shots - 1);
+    game.tick(fire(Vec2::Y));
+
+    let reach_enemy = ticks(&game, 1.0);
+    run(&mut game, reach_enemy);
+
+    assert_eq!(health(&game, target), 1);
+}
+
+#[test]
+fn piercing_bullet_hurts_each_enemy_once() {
+    let mut game = HeadlessGame::new(SEED);
+    let target = spawn_target(&mut game, Vec2::new(0.0, 200.0), 3);
+
+    // The piercing bullet is the second weapon
+    game.tick(PlayerInput {
+        switch_weapon: true,
+        ..Default::default()
+    });
+    game.tick(fire(Vec2::Y));
+
+    // Long enough to go through the target, but not to bounce back into it
+    let through_enemy = ticks(&game, 0.5);
+    run(&mut game, through_enemy);
+
+    assert_eq!(health(&game, target), 2);
+}